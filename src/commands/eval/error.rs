@@ -0,0 +1,25 @@
+use std::fmt;
+
+/// 計算式のどこで失敗したかを表すエラー。column は 1 始まりの文字数。
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl EvalError {
+    pub fn new(column: usize, message: impl Into<String>) -> Self {
+        Self {
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} 文字目: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for EvalError {}
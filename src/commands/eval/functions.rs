@@ -0,0 +1,107 @@
+use std::f64::consts;
+
+use num_traits::Signed;
+
+use super::number::Number;
+
+/// 組み込みの定数。変数と同じように名前で参照できるが、代入はできない
+pub fn constant(name: &str) -> Option<Number> {
+    match name {
+        "pi" => Some(Number::Float(consts::PI)),
+        "e" => Some(Number::Float(consts::E)),
+        _ => None,
+    }
+}
+
+/// 組み込みの関数と定数の名前。変数名には使えない
+pub fn is_builtin(name: &str) -> bool {
+    constant(name).is_some()
+        || matches!(
+            name,
+            "sqrt" | "log" | "sin" | "cos" | "tan" | "min" | "max" | "abs" | "round"
+        )
+}
+
+fn expect_args(name: &str, args: &[Number], count: usize) -> Result<(), String> {
+    if args.len() != count {
+        return Err(format!("{} は引数を {} つとります", name, count));
+    }
+    Ok(())
+}
+
+/// 組み込みの関数を呼び出す
+pub fn call(name: &str, args: &[Number]) -> Result<Number, String> {
+    match name {
+        "sqrt" => {
+            expect_args(name, args, 1)?;
+            if args[0].as_f64() < 0.0 {
+                return Err("負の数の平方根は計算できません".to_string());
+            }
+            Number::float(args[0].as_f64().sqrt())
+        }
+        // log(x) は自然対数、log(x, base) は base を底とする対数
+        "log" => {
+            let (x, base) = match args {
+                [x] => (x.as_f64(), consts::E),
+                [x, base] => (x.as_f64(), base.as_f64()),
+                _ => return Err("log は引数を 1 つか 2 つとります".to_string()),
+            };
+            if x <= 0.0 || base <= 0.0 || base == 1.0 {
+                return Err("log の引数は正の数 (底は 1 以外) にしてください".to_string());
+            }
+            Number::float(x.ln() / base.ln())
+        }
+        "sin" | "cos" | "tan" => {
+            expect_args(name, args, 1)?;
+            let x = args[0].as_f64();
+            Number::float(match name {
+                "sin" => x.sin(),
+                "cos" => x.cos(),
+                _ => x.tan(),
+            })
+        }
+        "min" | "max" => {
+            let mut args = args.iter().cloned();
+            let first = args
+                .next()
+                .ok_or_else(|| format!("{} は引数を 1 つ以上とります", name))?;
+            Ok(args.fold(first, |acc, n| {
+                let replace = match name {
+                    "min" => n.as_f64() < acc.as_f64(),
+                    _ => n.as_f64() > acc.as_f64(),
+                };
+                if replace {
+                    n
+                } else {
+                    acc
+                }
+            }))
+        }
+        "abs" => {
+            expect_args(name, args, 1)?;
+            match args[0].to_bigint() {
+                Some(n) => Number::big(n.abs()),
+                None => Ok(Number::Float(args[0].as_f64().abs())),
+            }
+        }
+        // round(x) は整数に、round(x, digits) は小数点以下 digits 桁に丸める
+        "round" => match args {
+            [n @ Number::Int(_)] | [n @ Number::Big(_)] => Ok(n.clone()),
+            [Number::Float(n)] => {
+                let rounded = n.round();
+                if rounded.abs() < i64::MAX as f64 {
+                    Ok(Number::Int(rounded as i64))
+                } else {
+                    Number::float(rounded)
+                }
+            }
+            [x, Number::Int(digits)] if (0..=15).contains(digits) => {
+                let scale = 10f64.powi(*digits as i32);
+                Number::float((x.as_f64() * scale).round() / scale)
+            }
+            [_, _] => Err("round の桁数は 0 から 15 の整数にしてください".to_string()),
+            _ => Err("round は引数を 1 つか 2 つとります".to_string()),
+        },
+        _ => Err(format!("{} という関数はありません", name)),
+    }
+}
@@ -0,0 +1,386 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::channel::AttachmentType;
+use serenity::model::id::UserId;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+use serenity::model::prelude::interaction::InteractionResponseType;
+use serenity::prelude::{Context, TypeMapKey};
+use tracing::error;
+
+mod error;
+mod functions;
+mod number;
+mod parser;
+mod tokenizer;
+mod units;
+mod value;
+
+use error::EvalError;
+use parser::parse;
+use tokenizer::tokenizer;
+use value::Value;
+
+// Discord のメッセージの文字数の上限
+const MESSAGE_LIMIT: usize = 2000;
+
+// 結果がメッセージに収まらない場合に表示する文字数
+const PREVIEW_LENGTH: usize = 1000;
+
+/// ユーザーごとに /eval で代入した変数。bot が再起動するまで保持する
+struct EvalSessions;
+
+impl TypeMapKey for EvalSessions {
+    type Value = HashMap<UserId, HashMap<String, Value>>;
+}
+
+// 途中でエラーになった場合は、それまでの代入も variables に反映しない
+fn safe_eval(
+    s: String,
+    precision: Option<usize>,
+    variables: &mut HashMap<String, Value>,
+) -> Result<String, EvalError> {
+    let end_column = s.chars().count() + 1;
+    let tokens = tokenizer(s)?;
+    let statements = parse(tokens, end_column)?;
+    let mut next_variables = variables.clone();
+    let result = parser::run(&statements, &mut next_variables)?;
+    *variables = next_variables;
+    Ok(result.format(precision))
+}
+
+/// 送信するメッセージと添付するファイルの中身。
+/// メッセージに収まらない場合は途中までを表示して、全体はファイルにする
+fn reply(content: String) -> (String, Option<String>) {
+    let length = content.chars().count();
+    if length <= MESSAGE_LIMIT {
+        return (content, None);
+    }
+    let preview = content.chars().take(PREVIEW_LENGTH).collect::<String>();
+    (
+        format!(
+            "{}…\n(全体で {} 文字あるのでファイルを添付します)",
+            preview, length
+        ),
+        Some(content),
+    )
+}
+
+#[test]
+fn test_reply() {
+    let (content, file) = reply("1 + 1 = 2".to_string());
+    assert_eq!(content, "1 + 1 = 2");
+    assert_eq!(file, None);
+
+    let long = format!("1000! = {}", "4".repeat(2568));
+    let (content, file) = reply(long.clone());
+    assert_eq!(
+        content,
+        format!(
+            "{}…\n(全体で 2576 文字あるのでファイルを添付します)",
+            &long[..PREVIEW_LENGTH]
+        )
+    );
+    assert_eq!(file, Some(long));
+}
+
+#[test]
+fn test_safe_eval() {
+    let eval1 = safe_eval(String::from("2 + 2"), None, &mut HashMap::new());
+    assert_eq!(eval1, Ok("4".to_string()));
+
+    let eval2 = safe_eval(String::from("2 + 2 * 2"), None, &mut HashMap::new());
+    assert_eq!(eval2, Ok("6".to_string()));
+
+    let eval3 = safe_eval(String::from("(1 + 1) * 10 / 5"), None, &mut HashMap::new());
+    assert_eq!(eval3, Ok("4".to_string()));
+
+    let eval4 = safe_eval(
+        String::from("0+(1+(2+(3+(4+(5+(6+(7+8)))))))-(0+(1+(2+(3+(4+(5+(6+(7+8))))))))"),
+        None,
+        &mut HashMap::new(),
+    );
+    assert_eq!(eval4, Ok("0".to_string()));
+
+    let eval5 = safe_eval(String::from("(2+1030/2)-2"), None, &mut HashMap::new());
+    assert_eq!(eval5, Ok("515".to_string()));
+
+    // 割り切れない割り算は小数になる
+    let eval6 = safe_eval(String::from("1/3"), None, &mut HashMap::new());
+    assert_eq!(eval6, Ok("0.3333333333".to_string()));
+
+    let eval7 = safe_eval(String::from("1/3"), Some(3), &mut HashMap::new());
+    assert_eq!(eval7, Ok("0.333".to_string()));
+
+    let eval8 = safe_eval(String::from("1.5*2 + 0.1 + 0.2"), None, &mut HashMap::new());
+    assert_eq!(eval8, Ok("3.3".to_string()));
+
+    let eval9 = safe_eval(String::from("6.02e23 * 2"), None, &mut HashMap::new());
+    assert_eq!(eval9, Ok("1.204e24".to_string()));
+
+    let eval10 = safe_eval(
+        String::from("2^-1 + 10/4 + 7%2.5"),
+        None,
+        &mut HashMap::new(),
+    );
+    assert_eq!(eval10, Ok("5".to_string()));
+
+    let eval11 = safe_eval(
+        String::from("9223372036854775807 + 1"),
+        None,
+        &mut HashMap::new(),
+    );
+    assert_eq!(eval11, Ok("9223372036854775808".to_string()));
+
+    let eval12 = safe_eval(String::from("1 / 0"), None, &mut HashMap::new());
+    assert_eq!(eval12, Err(EvalError::new(3, "0 で割ることはできません")));
+
+    let eval13 = safe_eval(String::from("-3 * -(2 - 5)"), None, &mut HashMap::new());
+    assert_eq!(eval13, Ok("-9".to_string()));
+
+    let eval14 = safe_eval(String::from("(1 + 2"), None, &mut HashMap::new());
+    assert_eq!(
+        eval14,
+        Err(EvalError::new(7, "1 文字目の ( が閉じられていません"))
+    );
+
+    let eval15 = safe_eval(
+        String::from("sqrt(16) + abs(-2) + max(1, 2.5, 2) + min(3, -1) + round(2.6)"),
+        None,
+        &mut HashMap::new(),
+    );
+    assert_eq!(eval15, Ok("10.5".to_string()));
+
+    let eval16 = safe_eval(
+        String::from("log(1000, 10) + log(e) + sin(pi / 2) + round(pi, 2)"),
+        None,
+        &mut HashMap::new(),
+    );
+    assert_eq!(eval16, Ok("8.14".to_string()));
+
+    let eval17 = safe_eval(String::from("sqrt(1, 2)"), None, &mut HashMap::new());
+    assert_eq!(eval17, Err(EvalError::new(1, "sqrt は引数を 1 つとります")));
+
+    let eval18 = safe_eval(String::from("2 * foo(1)"), None, &mut HashMap::new());
+    assert_eq!(eval18, Err(EvalError::new(5, "foo という関数はありません")));
+}
+
+#[test]
+fn test_safe_eval_big() {
+    let cases = [
+        ("2^64", "18446744073709551616"),
+        ("25!", "15511210043330985984000000"),
+        ("30! / 28!", "870"),
+        ("2^100 - 2^100 + 1", "1"),
+        ("0xffffffffffffffff + 1 in hex", "0x10000000000000000"),
+        ("abs(-9223372036854775808)", "9223372036854775808"),
+        ("99999999999999999999 % 7", "1"),
+    ];
+    for (input, expected) in cases.iter() {
+        assert_eq!(
+            safe_eval(input.to_string(), None, &mut HashMap::new()),
+            Ok(expected.to_string()),
+            "{}",
+            input
+        );
+    }
+
+    let eval1 = safe_eval(String::from("1000!"), None, &mut HashMap::new());
+    assert_eq!(eval1.map(|result| result.len()), Ok(2568));
+
+    let eval2 = safe_eval(String::from("10 ^ 10 ^ 10"), None, &mut HashMap::new());
+    assert_eq!(eval2, Err(EvalError::new(4, "計算結果が大きすぎます")));
+}
+
+#[test]
+fn test_safe_eval_units() {
+    let cases = [
+        ("0xff", "255"),
+        ("0b1010 + 0o17", "25"),
+        ("1.5GiB", "1.5 GiB"),
+        ("4096MiB in GiB", "4 GiB"),
+        ("1GiB / 1MiB", "1024"),
+        ("90min", "1.5 h"),
+        ("90min in s", "5400 s"),
+        ("1h - 15 min", "45 min"),
+        ("500MB in MiB", "476.8371582031 MiB"),
+        ("0xff in bin", "0b11111111"),
+        ("255 in hex", "0xff"),
+        ("0xf0 | 0x0f & 0xff", "255"),
+        ("1 << 10 in hex", "0x400"),
+        ("-8 >> 1", "-4"),
+        ("min(90min / 1min, 60)", "60"),
+    ];
+    for (input, expected) in cases.iter() {
+        assert_eq!(
+            safe_eval(input.to_string(), None, &mut HashMap::new()),
+            Ok(expected.to_string()),
+            "{}",
+            input
+        );
+    }
+
+    let eval1 = safe_eval(String::from("1GiB + 1s"), None, &mut HashMap::new());
+    assert_eq!(
+        eval1,
+        Err(EvalError::new(6, "単位の種類が違う値は計算できません"))
+    );
+
+    let eval2 = safe_eval(String::from("1.5 in hex"), None, &mut HashMap::new());
+    assert_eq!(
+        eval2,
+        Err(EvalError::new(5, "進数を変換できるのは整数だけです"))
+    );
+
+    let eval3 = safe_eval(String::from("1GiB in parsec"), None, &mut HashMap::new());
+    assert_eq!(
+        eval3,
+        Err(EvalError::new(9, "parsec という単位や進数はありません"))
+    );
+
+    let eval4 = safe_eval(String::from("sqrt(4GiB)"), None, &mut HashMap::new());
+    assert_eq!(
+        eval4,
+        Err(EvalError::new(1, "sqrt には単位のない数値を使ってください"))
+    );
+}
+
+#[test]
+fn test_safe_eval_variables() {
+    let mut variables = HashMap::new();
+    let eval1 = safe_eval(String::from("x = 3; x*2"), None, &mut variables);
+    assert_eq!(eval1, Ok("6".to_string()));
+
+    // 変数は次の呼び出しでも使える
+    let eval2 = safe_eval(String::from("y = x ^ 2; y + 1"), None, &mut variables);
+    assert_eq!(eval2, Ok("10".to_string()));
+
+    // 途中で失敗した場合は代入も取り消す
+    let eval3 = safe_eval(String::from("x = 100; x / 0"), None, &mut variables);
+    assert_eq!(eval3, Err(EvalError::new(12, "0 で割ることはできません")));
+    assert_eq!(
+        variables.get("x").map(|value| value.format(None)),
+        Some("3".to_string())
+    );
+
+    let eval4 = safe_eval(String::from("z + 1"), None, &mut variables);
+    assert_eq!(eval4, Err(EvalError::new(1, "z は定義されていません")));
+}
+
+pub async fn run(command: &ApplicationCommandInteraction, ctx: &Context) {
+    let content = eval(&command.data.options, ctx, command.user.id).await;
+    let (content, file) = reply(content);
+
+    if let Err(why) = command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    if let Some(file) = file {
+                        message.add_file(AttachmentType::Bytes {
+                            data: Cow::from(file.into_bytes()),
+                            filename: "eval.txt".to_string(),
+                        });
+                    }
+                    message.content(content)
+                })
+        })
+        .await
+    {
+        error!("failed to create interaction response: {:?}", why);
+    }
+}
+
+async fn eval(options: &[CommandDataOption], ctx: &Context, user_id: UserId) -> String {
+    let eval_target = match options.iter().find(|option| option.name == "eval") {
+        Some(option) => match &option.resolved {
+            Some(value) => match value {
+                CommandDataOptionValue::String(eval_target) => eval_target,
+                _ => return "計算式を入力してください".to_string(),
+            },
+            None => return "計算式を入力してください".to_string(),
+        },
+        None => return "計算式を入力してください".to_string(),
+    };
+
+    // 小数点以下の表示桁数。指定がなければ末尾の 0 を省いて表示する
+    let precision = options
+        .iter()
+        .find(|option| option.name == "precision")
+        .and_then(|option| match &option.resolved {
+            Some(CommandDataOptionValue::Integer(precision)) => usize::try_from(*precision).ok(),
+            _ => None,
+        });
+
+    // ctx.data は bot 全体で共有しているので、重い計算の間はロックを持たない
+    let mut variables = ctx
+        .data
+        .read()
+        .await
+        .get::<EvalSessions>()
+        .and_then(|sessions| sessions.get(&user_id))
+        .cloned()
+        .unwrap_or_default();
+
+    // 大きな数の計算は時間がかかるので async runtime の外で行う
+    let target = eval_target.to_string();
+    let evaluated = tokio::task::spawn_blocking(move || {
+        let result = safe_eval(target, precision, &mut variables);
+        (result, variables)
+    })
+    .await;
+    let result = match evaluated {
+        Ok((Ok(result), variables)) => {
+            ctx.data
+                .write()
+                .await
+                .entry::<EvalSessions>()
+                .or_insert_with(HashMap::new)
+                .insert(user_id, variables);
+            Ok(result)
+        }
+        Ok((Err(err), _)) => Err(err),
+        Err(why) => {
+            error!("failed to evaluate: {:?}", why);
+            return "計算に失敗しました".to_string();
+        }
+    };
+
+    match result {
+        Ok(result) => format!("{} = {}", eval_target, result),
+        // 失敗した位置を ^ で示す
+        Err(err) => format!(
+            "```\n{}\n{}^\n```\n{}",
+            eval_target,
+            " ".repeat(err.column.saturating_sub(1)),
+            err
+        ),
+    }
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("eval")
+        .description("演算します")
+        .create_option(|option| {
+            option
+                .name("eval")
+                .description("計算式。x = 3; x * 2 のような変数や 4096MiB in GiB, 0xff in bin のような変換も使えます")
+                .kind(CommandOptionType::String)
+                .required(true)
+        })
+        .create_option(|option| {
+            option
+                .name("precision")
+                .description("小数点以下の表示桁数")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(15)
+                .required(false)
+        })
+}
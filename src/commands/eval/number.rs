@@ -0,0 +1,275 @@
+use std::convert::TryFrom;
+
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+const OVERFLOW: &str = "計算結果が大きすぎます";
+const DIVIDE_BY_ZERO: &str = "0 で割ることはできません";
+
+// 整数のビット数の上限 (10 進数で約 31 万桁)。これを超える計算結果はエラーにする
+pub const MAX_BITS: u64 = 1 << 20;
+
+// 階乗を計算できる上限
+const MAX_FACTORIAL: i64 = 20_000;
+
+/// 計算途中の数値。整数同士の計算は整数のまま正確に扱い、i64 に収まらなくなったら多倍長整数にする。
+/// 割り切れない割り算や小数が含まれる場合は浮動小数点数にする。
+#[derive(Clone, PartialEq, Debug)]
+pub enum Number {
+    Int(i64),
+    // i64 に収まらない整数。i64 に収まる値は常に Int で表す
+    Big(BigInt),
+    Float(f64),
+}
+
+impl Number {
+    /// 多倍長整数を Number にする。i64 に収まる場合は Int にする
+    pub fn big(n: BigInt) -> Result<Number, String> {
+        if n.bits() > MAX_BITS {
+            return Err(OVERFLOW.to_string());
+        }
+        Ok(match n.to_i64() {
+            Some(n) => Number::Int(n),
+            None => Number::Big(n),
+        })
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Number::Int(n) => *n as f64,
+            Number::Big(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Float(n) => *n,
+        }
+    }
+
+    /// 整数の場合は多倍長整数として返す
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Number::Int(n) => Some(BigInt::from(*n)),
+            Number::Big(n) => Some(n.clone()),
+            Number::Float(_) => None,
+        }
+    }
+
+    // 浮動小数点数の計算結果が無限大や NaN になった場合はエラーにする
+    pub fn float(n: f64) -> Result<Number, String> {
+        if n.is_nan() {
+            Err("計算結果が数値になりません".to_string())
+        } else if n.is_infinite() {
+            Err(OVERFLOW.to_string())
+        } else {
+            Ok(Number::Float(n))
+        }
+    }
+
+    // 整数同士なら int_op、小数を含む場合は float_op で計算する
+    fn checked(
+        &self,
+        rhs: &Number,
+        int_op: fn(BigInt, BigInt) -> BigInt,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Result<Number, String> {
+        match (self.to_bigint(), rhs.to_bigint()) {
+            (Some(a), Some(b)) => Number::big(int_op(a, b)),
+            _ => Number::float(float_op(self.as_f64(), rhs.as_f64())),
+        }
+    }
+
+    pub fn add(self, rhs: Number) -> Result<Number, String> {
+        self.checked(&rhs, |a, b| a + b, |a, b| a + b)
+    }
+
+    pub fn sub(self, rhs: Number) -> Result<Number, String> {
+        self.checked(&rhs, |a, b| a - b, |a, b| a - b)
+    }
+
+    pub fn mul(self, rhs: Number) -> Result<Number, String> {
+        self.checked(&rhs, |a, b| a * b, |a, b| a * b)
+    }
+
+    // 割り切れる場合だけ整数のままにする
+    pub fn div(self, rhs: Number) -> Result<Number, String> {
+        if rhs.as_f64() == 0.0 {
+            return Err(DIVIDE_BY_ZERO.to_string());
+        }
+        match (self.to_bigint(), rhs.to_bigint()) {
+            (Some(a), Some(b)) if (&a % &b).is_zero() => Number::big(a / b),
+            _ => Number::float(self.as_f64() / rhs.as_f64()),
+        }
+    }
+
+    pub fn rem(self, rhs: Number) -> Result<Number, String> {
+        if rhs.as_f64() == 0.0 {
+            return Err(DIVIDE_BY_ZERO.to_string());
+        }
+        self.checked(&rhs, |a, b| a % b, |a, b| a % b)
+    }
+
+    // 指数が 0 以上の整数なら整数のまま計算する。結果が大きすぎる場合は計算する前にエラーにする
+    pub fn pow(self, rhs: Number) -> Result<Number, String> {
+        match (self.to_bigint(), &rhs) {
+            (Some(base), Number::Int(exponent)) if *exponent >= 0 => {
+                let exponent = u32::try_from(*exponent).map_err(|_| OVERFLOW.to_string())?;
+                if base.bits().saturating_sub(1) * u64::from(exponent) > MAX_BITS {
+                    return Err(OVERFLOW.to_string());
+                }
+                Number::big(base.pow(exponent))
+            }
+            _ => Number::float(self.as_f64().powf(rhs.as_f64())),
+        }
+    }
+
+    /// n! を計算する。n は 0 以上の整数だけ
+    pub fn factorial(self) -> Result<Number, String> {
+        let n = match self {
+            Number::Int(n) if n >= 0 => n,
+            // i64 に収まらない正の数は上限を超えている
+            Number::Big(n) if n.is_positive() => i64::MAX,
+            _ => return Err("階乗は 0 以上の整数にしか使えません".to_string()),
+        };
+        if n > MAX_FACTORIAL {
+            return Err(format!("階乗は {} までしか計算できません", MAX_FACTORIAL));
+        }
+        Number::big((2..=n).fold(BigInt::from(1), |acc, i| acc * i))
+    }
+
+    // ビット演算は整数同士にだけ使える
+    fn bitwise(
+        self,
+        rhs: Number,
+        op: fn(BigInt, BigInt) -> Result<BigInt, String>,
+    ) -> Result<Number, String> {
+        match (self.to_bigint(), rhs.to_bigint()) {
+            (Some(a), Some(b)) => Number::big(op(a, b)?),
+            _ => Err("ビット演算は整数にしか使えません".to_string()),
+        }
+    }
+
+    pub fn bitand(self, rhs: Number) -> Result<Number, String> {
+        self.bitwise(rhs, |a, b| Ok(a & b))
+    }
+
+    pub fn bitor(self, rhs: Number) -> Result<Number, String> {
+        self.bitwise(rhs, |a, b| Ok(a | b))
+    }
+
+    pub fn shl(self, rhs: Number) -> Result<Number, String> {
+        self.bitwise(rhs, |a, b| Ok(a << shift_amount(&b)?))
+    }
+
+    pub fn shr(self, rhs: Number) -> Result<Number, String> {
+        self.bitwise(rhs, |a, b| Ok(a >> shift_amount(&b)?))
+    }
+
+    pub fn neg(self) -> Result<Number, String> {
+        match self.to_bigint() {
+            Some(n) => Number::big(-n),
+            None => Ok(Number::Float(-self.as_f64())),
+        }
+    }
+
+    /// precision が指定された場合は小数点以下をその桁数で表示する。
+    /// 指定がない場合は小数点以下 10 桁までで末尾の 0 を取り除く。
+    pub fn format(&self, precision: Option<usize>) -> String {
+        let n = match self {
+            Number::Int(n) => return n.to_string(),
+            Number::Big(n) => return n.to_string(),
+            Number::Float(n) => *n,
+        };
+        if !n.is_finite() {
+            return n.to_string();
+        }
+
+        // 極端に大きい値や小さい値は指数表記にする
+        let abs = n.abs();
+        let scientific = abs >= 1e15 || (abs != 0.0 && abs < 1e-10);
+        match (precision, scientific) {
+            (Some(precision), true) => format!("{:.*e}", precision, n),
+            (Some(precision), false) => format!("{:.*}", precision, n),
+            (None, true) => format!("{:e}", n),
+            (None, false) => {
+                let formatted = format!("{:.10}", n);
+                let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+                match formatted {
+                    "-0" => "0".to_string(),
+                    formatted => formatted.to_string(),
+                }
+            }
+        }
+    }
+}
+
+fn shift_amount(n: &BigInt) -> Result<usize, String> {
+    n.to_u64()
+        .filter(|n| *n <= MAX_BITS)
+        .and_then(|n| usize::try_from(n).ok())
+        .ok_or_else(|| format!("シフトする量は 0 から {} の整数にしてください", MAX_BITS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> Number {
+        Number::Big(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_big() {
+        assert_eq!(
+            Number::Int(i64::MAX).add(Number::Int(1)),
+            Ok(big("9223372036854775808"))
+        );
+        // i64 に収まるようになったら Int に戻す
+        assert_eq!(
+            big("9223372036854775808").sub(Number::Int(1)),
+            Ok(Number::Int(i64::MAX))
+        );
+        assert_eq!(
+            Number::Int(2).pow(Number::Int(64)),
+            Ok(big("18446744073709551616"))
+        );
+        assert_eq!(
+            big("18446744073709551616").div(Number::Int(3)),
+            Ok(Number::Float(6148914691236517000.0))
+        );
+        assert_eq!(
+            big("18446744073709551616").div(Number::Int(4)),
+            Ok(Number::Int(4611686018427387904))
+        );
+        assert_eq!(Number::Int(i64::MIN).neg(), Ok(big("9223372036854775808")));
+        assert_eq!(
+            Number::Int(1).shl(Number::Int(64)),
+            Ok(big("18446744073709551616"))
+        );
+        assert_eq!(
+            Number::Int(10).pow(Number::Int(1_000_000)),
+            Err(OVERFLOW.to_string())
+        );
+    }
+
+    #[test]
+    fn test_factorial() {
+        assert_eq!(Number::Int(0).factorial(), Ok(Number::Int(1)));
+        assert_eq!(
+            Number::Int(20).factorial(),
+            Ok(Number::Int(2432902008176640000))
+        );
+        assert_eq!(
+            Number::Int(25).factorial(),
+            Ok(big("15511210043330985984000000"))
+        );
+        assert_eq!(
+            Number::Int(-1).factorial(),
+            Err("階乗は 0 以上の整数にしか使えません".to_string())
+        );
+        assert_eq!(
+            Number::Float(1.5).factorial(),
+            Err("階乗は 0 以上の整数にしか使えません".to_string())
+        );
+        assert_eq!(
+            Number::Int(20_001).factorial(),
+            Err("階乗は 20000 までしか計算できません".to_string())
+        );
+    }
+}
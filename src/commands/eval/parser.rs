@@ -0,0 +1,685 @@
+use std::collections::HashMap;
+
+use super::error::EvalError;
+use super::functions;
+use super::number::Number;
+use super::tokenizer::Token;
+use super::units::{self, Target, Unit};
+use super::value::Value;
+
+// 括弧や単項演算子の入れ子の上限。深すぎる式でスタックを使い切らないようにする
+const MAX_DEPTH: usize = 200;
+
+// 演算子と関数呼び出しの数の上限。1+1+…+1 のような長い式は入れ子にならなくても木が深くなり、
+// evaluate や Drop の再帰でスタックを使い切るので、木の節の数も制限する
+const MAX_NODES: usize = 200;
+
+// 1人のユーザーが保持できる変数の数
+const MAX_VARIABLES: usize = 64;
+
+// 単項の + と - の結合力。-2^2 は -(2^2)、-2*3 は (-2)*3 になる
+const PREFIX_BINDING_POWER: u8 = 11;
+
+// 後置の ! の結合力。-3! は -(3!)、2^3! は 2^(3!) になる
+const POSTFIX_BINDING_POWER: u8 = 14;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    BitAnd,
+    BitOr,
+    Shl,
+    Shr,
+}
+
+impl Operator {
+    fn from_token(token: &Token) -> Option<Operator> {
+        match token {
+            Token::Plus => Some(Operator::Add),
+            Token::Minus => Some(Operator::Sub),
+            Token::Star => Some(Operator::Mul),
+            Token::Slash => Some(Operator::Div),
+            Token::Percent => Some(Operator::Mod),
+            Token::Hat => Some(Operator::Pow),
+            Token::Ampersand => Some(Operator::BitAnd),
+            Token::Pipe => Some(Operator::BitOr),
+            Token::ShiftLeft => Some(Operator::Shl),
+            Token::ShiftRight => Some(Operator::Shr),
+            _ => None,
+        }
+    }
+
+    // (左の結合力, 右の結合力)。^ は右結合なので右の結合力を左より小さくする
+    // ビット演算の優先順位は C と同じで | < & < シフト < 足し算
+    fn binding_power(self) -> (u8, u8) {
+        match self {
+            Operator::BitOr => (1, 2),
+            Operator::BitAnd => (3, 4),
+            Operator::Shl | Operator::Shr => (5, 6),
+            Operator::Add | Operator::Sub => (7, 8),
+            Operator::Mul | Operator::Div | Operator::Mod => (9, 10),
+            Operator::Pow => (13, 12),
+        }
+    }
+
+    fn apply(self, left: Value, right: Value) -> Result<Value, String> {
+        match self {
+            Operator::Add => left.add(right),
+            Operator::Sub => left.sub(right),
+            Operator::Mul => left.mul(right),
+            Operator::Div => left.div(right),
+            Operator::Mod => left.rem(right),
+            Operator::Pow => left.pow(right),
+            Operator::BitAnd => left.bitand(right),
+            Operator::BitOr => left.bitor(right),
+            Operator::Shl => left.shl(right),
+            Operator::Shr => left.shr(right),
+        }
+    }
+}
+
+/// 計算式の構文木。演算子には実行時のエラーを示すための列番号を持たせる
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Number(Number),
+    // 1.5GiB のような単位つきの数値
+    Unit {
+        number: Number,
+        unit: &'static Unit,
+        column: usize,
+    },
+    // 定数か変数
+    Variable {
+        name: String,
+        column: usize,
+    },
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        column: usize,
+    },
+    Neg {
+        operand: Box<Expr>,
+        column: usize,
+    },
+    Factorial {
+        operand: Box<Expr>,
+        column: usize,
+    },
+    Binary {
+        op: Operator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        column: usize,
+    },
+    Convert {
+        expr: Box<Expr>,
+        target: Target,
+        column: usize,
+    },
+}
+
+/// ; で区切られた1つ1つの文。x = 3 のような代入か、式のどちらか
+#[derive(Clone, PartialEq, Debug)]
+pub enum Statement {
+    Assign {
+        name: String,
+        expr: Expr,
+        column: usize,
+    },
+    Expr(Expr),
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    depth: usize,
+    nodes: usize,
+    end_column: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(Token, usize)> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // 演算子や関数呼び出しの節を 1 つ作る
+    fn node(&mut self, column: usize) -> Result<(), EvalError> {
+        self.nodes += 1;
+        if self.nodes > MAX_NODES {
+            return Err(EvalError::new(column, "式が長すぎます"));
+        }
+        Ok(())
+    }
+
+    // min_binding_power より強く結合する演算子だけを読み進める (Pratt parser)
+    fn expr(&mut self, min_binding_power: u8) -> Result<Expr, EvalError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            let column = self.peek().map_or(self.end_column, |(_, column)| *column);
+            return Err(EvalError::new(column, "式の入れ子が深すぎます"));
+        }
+
+        let mut left = self.prefix()?;
+        while let Some((token, column)) = self.peek() {
+            let column = *column;
+            // in は一番弱く結合するので 1 + 2 in hex は (1 + 2) in hex になる
+            if *token == Token::In {
+                if min_binding_power > 0 {
+                    break;
+                }
+                self.next();
+                self.node(column)?;
+                left = Expr::Convert {
+                    expr: Box::new(left),
+                    target: self.target()?,
+                    column,
+                };
+                continue;
+            }
+            if *token == Token::Bang {
+                if POSTFIX_BINDING_POWER < min_binding_power {
+                    break;
+                }
+                self.next();
+                self.node(column)?;
+                left = Expr::Factorial {
+                    operand: Box::new(left),
+                    column,
+                };
+                continue;
+            }
+            let op = match Operator::from_token(token) {
+                Some(op) => op,
+                None => break,
+            };
+            let (left_binding_power, right_binding_power) = op.binding_power();
+            if left_binding_power < min_binding_power {
+                break;
+            }
+            self.next();
+            self.node(column)?;
+            let right = self.expr(right_binding_power)?;
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+                column,
+            };
+        }
+
+        self.depth -= 1;
+        Ok(left)
+    }
+
+    // 数値、名前、関数呼び出し、括弧、単項演算子を読む
+    fn prefix(&mut self) -> Result<Expr, EvalError> {
+        match self.next() {
+            Some((Token::Int(n), column)) => Ok(self.number(Number::Int(n), column)),
+            Some((Token::Big(n), column)) => Ok(self.number(Number::Big(n), column)),
+            Some((Token::Float(n), column)) => Ok(self.number(Number::Float(n), column)),
+            Some((Token::Ident(name), column)) => {
+                if !matches!(self.peek(), Some((Token::LeftParen, _))) {
+                    return Ok(Expr::Variable { name, column });
+                }
+                let open = self.next().map_or(column, |(_, column)| column);
+                self.node(column)?;
+                let args = self.args(open)?;
+                Ok(Expr::Call { name, args, column })
+            }
+            Some((Token::Minus, column)) => {
+                self.node(column)?;
+                Ok(Expr::Neg {
+                    operand: Box::new(self.expr(PREFIX_BINDING_POWER)?),
+                    column,
+                })
+            }
+            Some((Token::Plus, _)) => self.expr(PREFIX_BINDING_POWER),
+            Some((Token::LeftParen, open)) => {
+                let expr = self.expr(0)?;
+                match self.next() {
+                    Some((Token::RightParen, _)) => Ok(expr),
+                    Some((token, column)) => Err(unexpected(&token, column)),
+                    None => Err(EvalError::new(
+                        self.end_column,
+                        format!("{} 文字目の ( が閉じられていません", open),
+                    )),
+                }
+            }
+            Some((token, column)) => Err(unexpected(&token, column)),
+            None => Err(EvalError::new(self.end_column, "式が途中で終わっています")),
+        }
+    }
+}
+
+impl Parser {
+    // 数値の直後に単位の名前があれば単位つきの数値にする。min( のような関数呼び出しは単位にしない
+    fn number(&mut self, number: Number, column: usize) -> Expr {
+        let unit = match (self.peek(), self.tokens.get(self.position + 1)) {
+            (_, Some((Token::LeftParen, _))) => None,
+            (Some((Token::Ident(name), _)), _) => units::find_unit(name),
+            _ => None,
+        };
+        match unit {
+            Some(unit) => {
+                self.next();
+                Expr::Unit {
+                    number,
+                    unit,
+                    column,
+                }
+            }
+            None => Expr::Number(number),
+        }
+    }
+
+    // in の後の単位か進数の名前を読む
+    fn target(&mut self) -> Result<Target, EvalError> {
+        match self.next() {
+            Some((Token::Ident(name), column)) => units::find_target(&name).ok_or_else(|| {
+                EvalError::new(column, format!("{} という単位や進数はありません", name))
+            }),
+            Some((token, column)) => Err(unexpected(&token, column)),
+            None => Err(EvalError::new(self.end_column, "式が途中で終わっています")),
+        }
+    }
+
+    // 関数の引数を , で区切って ) まで読む。open は ( の列番号
+    fn args(&mut self, open: usize) -> Result<Vec<Expr>, EvalError> {
+        let mut args = Vec::new();
+        if let Some((Token::RightParen, _)) = self.peek() {
+            self.next();
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr(0)?);
+            match self.next() {
+                Some((Token::Comma, _)) => continue,
+                Some((Token::RightParen, _)) => return Ok(args),
+                Some((token, column)) => return Err(unexpected(&token, column)),
+                None => {
+                    return Err(EvalError::new(
+                        self.end_column,
+                        format!("{} 文字目の ( が閉じられていません", open),
+                    ))
+                }
+            }
+        }
+    }
+
+    // 名前の後に = が続いていれば代入、そうでなければ式として読む
+    fn statement(&mut self) -> Result<Statement, EvalError> {
+        let assign = match (
+            self.tokens.get(self.position),
+            self.tokens.get(self.position + 1),
+        ) {
+            (Some((Token::Ident(name), column)), Some((Token::Equal, _))) => {
+                Some((name.clone(), *column))
+            }
+            _ => None,
+        };
+        match assign {
+            Some((name, column)) => {
+                self.position += 2;
+                let expr = self.expr(0)?;
+                Ok(Statement::Assign { name, expr, column })
+            }
+            None => Ok(Statement::Expr(self.expr(0)?)),
+        }
+    }
+}
+
+fn unexpected(token: &Token, column: usize) -> EvalError {
+    EvalError::new(column, format!("予期しない {} があります", token))
+}
+
+/// token を ; で区切られた文の列にする。end_column は式の末尾の次の列番号で、式が途中で終わっている場合に使う
+pub fn parse(tokens: Vec<(Token, usize)>, end_column: usize) -> Result<Vec<Statement>, EvalError> {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        depth: 0,
+        nodes: 0,
+        end_column,
+    };
+    let mut statements = Vec::new();
+    loop {
+        // 空の文 (;; や末尾の ;) は読み飛ばす
+        match parser.peek() {
+            None => return Ok(statements),
+            Some((Token::Semicolon, _)) => {
+                parser.next();
+                continue;
+            }
+            Some(_) => (),
+        }
+        statements.push(parser.statement()?);
+        match parser.next() {
+            Some((Token::Semicolon, _)) | None => (),
+            Some((token, column)) => return Err(unexpected(&token, column)),
+        }
+    }
+}
+
+/// 構文木を計算する。オーバーフローや 0 除算は演算子の位置を示すエラーにする
+pub fn evaluate(expr: &Expr, variables: &HashMap<String, Value>) -> Result<Value, EvalError> {
+    match expr {
+        Expr::Number(n) => Ok(Value::from(n.clone())),
+        Expr::Unit {
+            number,
+            unit,
+            column,
+        } => Value::with_unit(number.clone(), unit).map_err(|why| EvalError::new(*column, why)),
+        Expr::Variable { name, column } => functions::constant(name)
+            .map(Value::from)
+            .or_else(|| variables.get(name).cloned())
+            .ok_or_else(|| EvalError::new(*column, format!("{} は定義されていません", name))),
+        // 関数には単位のない数値だけを渡せる
+        Expr::Call { name, args, column } => {
+            let args = args
+                .iter()
+                .map(|arg| {
+                    evaluate(arg, variables)?
+                        .plain(name)
+                        .map_err(|why| EvalError::new(*column, why))
+                })
+                .collect::<Result<Vec<Number>, EvalError>>()?;
+            functions::call(name, &args)
+                .map(Value::from)
+                .map_err(|why| EvalError::new(*column, why))
+        }
+        Expr::Neg { operand, column } => evaluate(operand, variables)?
+            .neg()
+            .map_err(|why| EvalError::new(*column, why)),
+        Expr::Factorial { operand, column } => evaluate(operand, variables)?
+            .factorial()
+            .map_err(|why| EvalError::new(*column, why)),
+        Expr::Binary {
+            op,
+            left,
+            right,
+            column,
+        } => {
+            let left = evaluate(left, variables)?;
+            let right = evaluate(right, variables)?;
+            op.apply(left, right)
+                .map_err(|why| EvalError::new(*column, why))
+        }
+        Expr::Convert {
+            expr,
+            target,
+            column,
+        } => evaluate(expr, variables)?
+            .convert(*target)
+            .map_err(|why| EvalError::new(*column, why)),
+    }
+}
+
+/// 文を順番に実行して最後の文の値を返す。代入した変数は variables に残る
+pub fn run(
+    statements: &[Statement],
+    variables: &mut HashMap<String, Value>,
+) -> Result<Value, EvalError> {
+    let mut result = None;
+    for statement in statements {
+        let value = match statement {
+            Statement::Assign { name, expr, column } => {
+                if functions::is_builtin(name) {
+                    return Err(EvalError::new(
+                        *column,
+                        format!("{} は組み込みの名前なので代入できません", name),
+                    ));
+                }
+                if !variables.contains_key(name) && variables.len() >= MAX_VARIABLES {
+                    return Err(EvalError::new(
+                        *column,
+                        format!("変数は {} 個までしか定義できません", MAX_VARIABLES),
+                    ));
+                }
+                let value = evaluate(expr, variables)?;
+                variables.insert(name.clone(), value.clone());
+                value
+            }
+            Statement::Expr(expr) => evaluate(expr, variables)?,
+        };
+        result = Some(value);
+    }
+    result.ok_or_else(|| EvalError::new(1, "計算式がありません"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_tokens(tokens: Vec<Token>) -> Result<Number, EvalError> {
+        let end_column = tokens.len() + 1;
+        let tokens = tokens
+            .into_iter()
+            .enumerate()
+            .map(|(i, token)| (token, i + 1))
+            .collect();
+        run(&parse(tokens, end_column)?, &mut HashMap::new()).map(|value| value.number)
+    }
+
+    #[test]
+    fn parse_test() {
+        let tokens1 = vec![Token::Int(2), Token::Plus, Token::Int(2)];
+        let result1 = eval_tokens(tokens1);
+        assert_eq!(result1, Ok(Number::Int(4)));
+
+        let tokens2 = vec![
+            Token::Int(2),
+            Token::Plus,
+            Token::Int(2),
+            Token::Star,
+            Token::Int(2),
+        ];
+        let result2 = eval_tokens(tokens2);
+        assert_eq!(result2, Ok(Number::Int(6)));
+
+        let tokens3 = vec![
+            Token::LeftParen,
+            Token::Int(1),
+            Token::Plus,
+            Token::Int(1),
+            Token::RightParen,
+            Token::Star,
+            Token::Int(10),
+            Token::Slash,
+            Token::Int(5),
+        ];
+        let result3 = eval_tokens(tokens3);
+        assert_eq!(result3, Ok(Number::Int(4)));
+
+        let tokens4 = vec![
+            Token::LeftParen,
+            Token::Int(10000),
+            Token::Plus,
+            Token::Int(1),
+            Token::RightParen,
+            Token::Star,
+            Token::LeftParen,
+            Token::Int(10),
+            Token::Slash,
+            Token::Int(5),
+            Token::RightParen,
+        ];
+        let result4 = eval_tokens(tokens4);
+        assert_eq!(result4, Ok(Number::Int(20002)));
+    }
+
+    #[test]
+    fn test_precedence() {
+        // -2^2 は -(2^2)、^ は右結合
+        let tokens = vec![Token::Minus, Token::Int(2), Token::Hat, Token::Int(2)];
+        assert_eq!(eval_tokens(tokens), Ok(Number::Int(-4)));
+        let tokens = vec![
+            Token::Int(2),
+            Token::Hat,
+            Token::Int(3),
+            Token::Hat,
+            Token::Int(2),
+        ];
+        assert_eq!(eval_tokens(tokens), Ok(Number::Int(512)));
+        let tokens = vec![Token::Int(2), Token::Hat, Token::Minus, Token::Int(1)];
+        assert_eq!(eval_tokens(tokens), Ok(Number::Float(0.5)));
+        let tokens = vec![
+            Token::Minus,
+            Token::Minus,
+            Token::Int(3),
+            Token::Star,
+            Token::Plus,
+            Token::Int(2),
+        ];
+        assert_eq!(eval_tokens(tokens), Ok(Number::Int(6)));
+        // -3! は -(3!)、2^3! は 2^(3!)
+        let tokens = vec![Token::Minus, Token::Int(3), Token::Bang];
+        assert_eq!(eval_tokens(tokens), Ok(Number::Int(-6)));
+        let tokens = vec![Token::Int(2), Token::Hat, Token::Int(3), Token::Bang];
+        assert_eq!(eval_tokens(tokens), Ok(Number::Int(64)));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let tokens = vec![Token::Int(1), Token::Plus];
+        assert_eq!(
+            eval_tokens(tokens),
+            Err(EvalError::new(3, "式が途中で終わっています"))
+        );
+        let tokens = vec![Token::RightParen, Token::LeftParen];
+        assert_eq!(
+            eval_tokens(tokens),
+            Err(EvalError::new(1, "予期しない ) があります"))
+        );
+        let tokens = vec![Token::LeftParen, Token::Int(1)];
+        assert_eq!(
+            eval_tokens(tokens),
+            Err(EvalError::new(3, "1 文字目の ( が閉じられていません"))
+        );
+        let tokens = vec![Token::Int(1), Token::Int(2)];
+        assert_eq!(
+            eval_tokens(tokens),
+            Err(EvalError::new(2, "予期しない 2 があります"))
+        );
+        let tokens = vec![Token::Minus; 1000];
+        assert_eq!(
+            eval_tokens(tokens).map_err(|why| why.message),
+            Err("式の入れ子が深すぎます".to_string())
+        );
+
+        // 入れ子にならない長い式も木が深くなるので制限する
+        let chain = |terms: usize| {
+            let mut tokens = vec![Token::Int(1)];
+            for _ in 1..terms {
+                tokens.push(Token::Plus);
+                tokens.push(Token::Int(1));
+            }
+            tokens
+        };
+        assert_eq!(eval_tokens(chain(200)), Ok(Number::Int(200)));
+        assert_eq!(
+            eval_tokens(chain(100_000)).map_err(|why| why.message),
+            Err("式が長すぎます".to_string())
+        );
+    }
+
+    #[test]
+    fn test_evaluate_error() {
+        let tokens = vec![Token::Int(2), Token::Hat, Token::Int(2_000_000)];
+        assert_eq!(
+            eval_tokens(tokens),
+            Err(EvalError::new(2, "計算結果が大きすぎます"))
+        );
+        let tokens = vec![
+            Token::LeftParen,
+            Token::Minus,
+            Token::Int(1),
+            Token::RightParen,
+            Token::Bang,
+        ];
+        assert_eq!(
+            eval_tokens(tokens),
+            Err(EvalError::new(5, "階乗は 0 以上の整数にしか使えません"))
+        );
+        let tokens = vec![
+            Token::Int(1),
+            Token::Plus,
+            Token::Int(1),
+            Token::Percent,
+            Token::Int(0),
+        ];
+        assert_eq!(
+            eval_tokens(tokens),
+            Err(EvalError::new(4, "0 で割ることはできません"))
+        );
+    }
+
+    #[test]
+    fn test_statements() {
+        let mut variables = HashMap::new();
+        let tokens = vec![
+            Token::Ident("x".to_string()),
+            Token::Equal,
+            Token::Int(3),
+            Token::Semicolon,
+            Token::Ident("x".to_string()),
+            Token::Star,
+            Token::Int(2),
+            Token::Semicolon,
+        ];
+        let statements = parse(tokens.into_iter().map(|token| (token, 1)).collect(), 1).unwrap();
+        assert_eq!(
+            run(&statements, &mut variables),
+            Ok(Value::from(Number::Int(6)))
+        );
+        assert_eq!(variables.get("x"), Some(&Value::from(Number::Int(3))));
+
+        let tokens = vec![Token::Ident("pi".to_string()), Token::Equal, Token::Int(3)];
+        let statements = parse(tokens.into_iter().map(|token| (token, 1)).collect(), 1).unwrap();
+        assert_eq!(
+            run(&statements, &mut variables),
+            Err(EvalError::new(1, "pi は組み込みの名前なので代入できません"))
+        );
+    }
+
+    #[test]
+    fn test_bitwise_precedence() {
+        // 1 | 2 & 3 << 1 + 1 は 1 | (2 & (3 << (1 + 1)))
+        let tokens = vec![
+            Token::Int(1),
+            Token::Pipe,
+            Token::Int(6),
+            Token::Ampersand,
+            Token::Int(3),
+            Token::ShiftLeft,
+            Token::Int(1),
+            Token::Plus,
+            Token::Int(1),
+        ];
+        assert_eq!(eval_tokens(tokens), Ok(Number::Int(5)));
+        let tokens = vec![Token::Int(1), Token::ShiftLeft, Token::Minus, Token::Int(1)];
+        assert_eq!(
+            eval_tokens(tokens),
+            Err(EvalError::new(
+                2,
+                "シフトする量は 0 から 1048576 の整数にしてください"
+            ))
+        );
+        let tokens = vec![Token::Float(1.5), Token::Ampersand, Token::Int(1)];
+        assert_eq!(
+            eval_tokens(tokens),
+            Err(EvalError::new(2, "ビット演算は整数にしか使えません"))
+        );
+    }
+}
@@ -0,0 +1,423 @@
+use std::fmt;
+
+use num_bigint::BigInt;
+
+use super::error::EvalError;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Token {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Hat,
+    LeftParen,
+    RightParen,
+    Comma,
+    Equal,
+    Semicolon,
+    Ampersand,
+    Pipe,
+    ShiftLeft,
+    ShiftRight,
+    // 4096MiB in GiB のような単位や進数の変換
+    In,
+    // 階乗
+    Bang,
+
+    Int(i64),
+    // i64 に収まらない整数
+    Big(BigInt),
+    Float(f64),
+    // 関数名、定数、変数
+    Ident(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Star => write!(f, "*"),
+            Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::Hat => write!(f, "^"),
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::Equal => write!(f, "="),
+            Token::Semicolon => write!(f, ";"),
+            Token::Ampersand => write!(f, "&"),
+            Token::Pipe => write!(f, "|"),
+            Token::ShiftLeft => write!(f, "<<"),
+            Token::ShiftRight => write!(f, ">>"),
+            Token::In => write!(f, "in"),
+            Token::Bang => write!(f, "!"),
+            Token::Int(n) => write!(f, "{}", n),
+            Token::Big(n) => write!(f, "{}", n),
+            Token::Float(n) => write!(f, "{}", n),
+            Token::Ident(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+fn char_to_token(c: char) -> Option<Token> {
+    match c {
+        '+' => Some(Token::Plus),
+        '-' => Some(Token::Minus),
+        '*' => Some(Token::Star),
+        '/' => Some(Token::Slash),
+        '%' => Some(Token::Percent),
+        '^' => Some(Token::Hat),
+        '(' => Some(Token::LeftParen),
+        ')' => Some(Token::RightParen),
+        ',' => Some(Token::Comma),
+        '=' => Some(Token::Equal),
+        ';' => Some(Token::Semicolon),
+        '&' => Some(Token::Ampersand),
+        '|' => Some(Token::Pipe),
+        '!' => Some(Token::Bang),
+        _ => None,
+    }
+}
+
+fn is_digit(c: char) -> bool {
+    c.is_digit(10)
+}
+
+// 0xff, 0b1010, 0o17 のような 16 進数、2 進数、8 進数を読む。接頭辞の後に数字がなければ None
+fn read_radix_number(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let radix = match chars.get(start + 1) {
+        Some('x') | Some('X') => 16,
+        Some('b') | Some('B') => 2,
+        Some('o') | Some('O') => 8,
+        _ => return None,
+    };
+    let digits_start = start + 2;
+    let end = chars[digits_start..]
+        .iter()
+        .position(|c| !c.is_digit(radix))
+        .map_or(chars.len(), |len| digits_start + len);
+    if end == digits_start {
+        return None;
+    }
+
+    let digits = chars[digits_start..end].iter().collect::<String>();
+    let token = match i64::from_str_radix(&digits, radix) {
+        Ok(n) => Token::Int(n),
+        Err(_) => Token::Big(BigInt::parse_bytes(digits.as_bytes(), radix)?),
+    };
+    Some((token, end))
+}
+
+// 小数点と指数 (1.5, .5, 1e-3, 2.5E+10) を含む数値を読む。
+// 小数点も指数もなければ整数、i64 に収まらない整数は多倍長整数にする。
+fn read_number(chars: &[char], start: usize) -> (Token, usize) {
+    if chars[start] == '0' {
+        if let Some(number) = read_radix_number(chars, start) {
+            return number;
+        }
+    }
+
+    let mut end = start;
+    while end < chars.len() && is_digit(chars[end]) {
+        end += 1;
+    }
+
+    let mut is_float = false;
+    if end < chars.len() && chars[end] == '.' {
+        is_float = true;
+        end += 1;
+        while end < chars.len() && is_digit(chars[end]) {
+            end += 1;
+        }
+    }
+
+    // e の後に数字が続く場合だけ指数として読む
+    if end < chars.len() && (chars[end] == 'e' || chars[end] == 'E') {
+        let mut exponent = end + 1;
+        if exponent < chars.len() && (chars[exponent] == '+' || chars[exponent] == '-') {
+            exponent += 1;
+        }
+        if exponent < chars.len() && is_digit(chars[exponent]) {
+            is_float = true;
+            end = exponent;
+            while end < chars.len() && is_digit(chars[end]) {
+                end += 1;
+            }
+        }
+    }
+
+    let literal = chars[start..end].iter().collect::<String>();
+    let token = if is_float {
+        Token::Float(literal.parse().unwrap_or(f64::INFINITY))
+    } else {
+        match literal.parse::<i64>() {
+            Ok(n) => Token::Int(n),
+            // 数字だけなので多倍長整数としては必ず読める
+            Err(_) => Token::Big(literal.parse().unwrap_or_default()),
+        }
+    };
+    (token, end)
+}
+
+/// 計算式を token に分ける。エラーの位置を示せるように token ごとに 1 始まりの列番号を返す。
+pub fn tokenizer(input: String) -> Result<Vec<(Token, usize)>, EvalError> {
+    let mut tokens = Vec::new();
+    let chars = input.chars().collect::<Vec<char>>();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        // 空白は無視
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        // 数字の場合は数値として読めるところまで読む
+        let starts_number =
+            is_digit(c) || (c == '.' && i + 1 < chars.len() && is_digit(chars[i + 1]));
+        if starts_number {
+            let (token, end) = read_number(&chars, i);
+            tokens.push((token, i + 1));
+            i = end;
+            continue;
+        }
+
+        // 英字か _ で始まる場合は名前として読む
+        if c.is_ascii_alphabetic() || c == '_' {
+            let end = chars[i..]
+                .iter()
+                .position(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+                .map_or(chars.len(), |len| i + len);
+            let name = chars[i..end].iter().collect::<String>();
+            let token = match name.as_str() {
+                "in" => Token::In,
+                _ => Token::Ident(name),
+            };
+            tokens.push((token, i + 1));
+            i = end;
+            continue;
+        }
+
+        // << と >> は 2 文字で 1 つの token
+        let shift = match (c, chars.get(i + 1)) {
+            ('<', Some('<')) => Some(Token::ShiftLeft),
+            ('>', Some('>')) => Some(Token::ShiftRight),
+            _ => None,
+        };
+        if let Some(token) = shift {
+            tokens.push((token, i + 1));
+            i += 2;
+            continue;
+        }
+
+        // それ以外の文字はトークンに変換
+        if let Some(token) = char_to_token(c) {
+            tokens.push((token, i + 1));
+            i += 1;
+            continue;
+        }
+
+        // 数字以外で token に変換できない文字が来たらエラー
+        return Err(EvalError::new(
+            i + 1,
+            format!("使えない文字 {} があります", c),
+        ));
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tokenizer_tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Result<Vec<Token>, EvalError> {
+        tokenizer(input.to_string())
+            .map(|tokens| tokens.into_iter().map(|(token, _)| token).collect())
+    }
+
+    #[test]
+    fn test_char_to_token() {
+        assert_eq!(char_to_token('+'), Some(Token::Plus));
+        assert_eq!(char_to_token('-'), Some(Token::Minus));
+        assert_eq!(char_to_token('*'), Some(Token::Star));
+        assert_eq!(char_to_token('/'), Some(Token::Slash));
+        assert_eq!(char_to_token('%'), Some(Token::Percent));
+        assert_eq!(char_to_token('^'), Some(Token::Hat));
+        assert_eq!(char_to_token('('), Some(Token::LeftParen));
+        assert_eq!(char_to_token(')'), Some(Token::RightParen));
+        assert_eq!(char_to_token(','), Some(Token::Comma));
+        assert_eq!(char_to_token('='), Some(Token::Equal));
+        assert_eq!(char_to_token(';'), Some(Token::Semicolon));
+        assert_eq!(char_to_token('&'), Some(Token::Ampersand));
+        assert_eq!(char_to_token('|'), Some(Token::Pipe));
+        assert_eq!(char_to_token('a'), None);
+    }
+
+    #[test]
+    fn test_is_digit() {
+        assert_eq!(is_digit('0'), true);
+        assert_eq!(is_digit('1'), true);
+        assert_eq!(is_digit('2'), true);
+        assert_eq!(is_digit('3'), true);
+        assert_eq!(is_digit('4'), true);
+        assert_eq!(is_digit('5'), true);
+        assert_eq!(is_digit('6'), true);
+        assert_eq!(is_digit('7'), true);
+        assert_eq!(is_digit('8'), true);
+        assert_eq!(is_digit('9'), true);
+        assert_eq!(is_digit('a'), false);
+    }
+
+    #[test]
+    fn test_tokenizer() {
+        let tokens1 = tokens("2 + 2");
+        assert_eq!(
+            tokens1,
+            Ok(vec![Token::Int(2), Token::Plus, Token::Int(2),])
+        );
+
+        let tokens2 = tokens("2 + 2 * 2");
+        assert_eq!(
+            tokens2,
+            Ok(vec![
+                Token::Int(2),
+                Token::Plus,
+                Token::Int(2),
+                Token::Star,
+                Token::Int(2),
+            ])
+        );
+
+        let tokens3 = tokens("(1 + 1) * 10 / 5");
+        assert_eq!(
+            tokens3,
+            Ok(vec![
+                Token::LeftParen,
+                Token::Int(1),
+                Token::Plus,
+                Token::Int(1),
+                Token::RightParen,
+                Token::Star,
+                Token::Int(10),
+                Token::Slash,
+                Token::Int(5),
+            ])
+        );
+
+        let tokens4 = tokens("1000");
+        assert_eq!(tokens4, Ok(vec![Token::Int(1000)]));
+
+        let tokens5 = tokens("1.5 * .5 + 2e3 - 1.5E-2");
+        assert_eq!(
+            tokens5,
+            Ok(vec![
+                Token::Float(1.5),
+                Token::Star,
+                Token::Float(0.5),
+                Token::Plus,
+                Token::Float(2000.0),
+                Token::Minus,
+                Token::Float(0.015),
+            ])
+        );
+
+        // i64 に収まらない整数は多倍長整数にする
+        let tokens6 = tokens("99999999999999999999 + 20!");
+        assert_eq!(
+            tokens6,
+            Ok(vec![
+                Token::Big("99999999999999999999".parse().unwrap()),
+                Token::Plus,
+                Token::Int(20),
+                Token::Bang,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_ident() {
+        assert_eq!(
+            tokens("x_1 = max(2e3, 2e); x_1*pi"),
+            Ok(vec![
+                Token::Ident("x_1".to_string()),
+                Token::Equal,
+                Token::Ident("max".to_string()),
+                Token::LeftParen,
+                Token::Float(2000.0),
+                Token::Comma,
+                Token::Int(2),
+                Token::Ident("e".to_string()),
+                Token::RightParen,
+                Token::Semicolon,
+                Token::Ident("x_1".to_string()),
+                Token::Star,
+                Token::Ident("pi".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_radix_and_units() {
+        assert_eq!(
+            tokens("0xff | 0b1010 & 0o17 << 1 >> 0X1F"),
+            Ok(vec![
+                Token::Int(255),
+                Token::Pipe,
+                Token::Int(10),
+                Token::Ampersand,
+                Token::Int(15),
+                Token::ShiftLeft,
+                Token::Int(1),
+                Token::ShiftRight,
+                Token::Int(31),
+            ])
+        );
+        assert_eq!(
+            tokens("1.5GiB + 90min in h"),
+            Ok(vec![
+                Token::Float(1.5),
+                Token::Ident("GiB".to_string()),
+                Token::Plus,
+                Token::Int(90),
+                Token::Ident("min".to_string()),
+                Token::In,
+                Token::Ident("h".to_string()),
+            ])
+        );
+        // 接頭辞の後に数字がなければ 0 と名前として読む
+        assert_eq!(
+            tokens("0b"),
+            Ok(vec![Token::Int(0), Token::Ident("b".to_string())])
+        );
+        assert_eq!(
+            tokens("0xffffffffffffffff"),
+            Ok(vec![Token::Big(BigInt::from(u64::MAX))])
+        );
+        assert_eq!(
+            tokens("1 < 2"),
+            Err(EvalError::new(3, "使えない文字 < があります"))
+        );
+    }
+
+    #[test]
+    fn test_tokenizer_column() {
+        assert_eq!(
+            tokenizer(String::from("12 + .5")),
+            Ok(vec![
+                (Token::Int(12), 1),
+                (Token::Plus, 4),
+                (Token::Float(0.5), 6)
+            ])
+        );
+        assert_eq!(
+            tokenizer(String::from("1 + あ")),
+            Err(EvalError::new(5, "使えない文字 あ があります"))
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use num_bigint::BigInt;
+use num_traits::Signed;
+
+use super::number::Number;
+
+/// 単位の種類。種類が同じ単位同士だけ足し算や変換ができる
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dimension {
+    // バイト数
+    Data,
+    // ミリ秒
+    Time,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Unit {
+    pub name: &'static str,
+    pub dimension: Dimension,
+    // 基本の単位 (バイト、ミリ秒) でいくつ分か
+    pub factor: i64,
+    // in で指定しなかった場合に表示で使う単位かどうか
+    auto: bool,
+}
+
+const fn unit(name: &'static str, dimension: Dimension, factor: i64, auto: bool) -> Unit {
+    Unit {
+        name,
+        dimension,
+        factor,
+        auto,
+    }
+}
+
+// auto の単位は factor の小さい順に並べる
+static UNITS: [Unit; 18] = [
+    unit("B", Dimension::Data, 1, true),
+    unit("KiB", Dimension::Data, 1 << 10, true),
+    unit("MiB", Dimension::Data, 1 << 20, true),
+    unit("GiB", Dimension::Data, 1 << 30, true),
+    unit("TiB", Dimension::Data, 1 << 40, true),
+    unit("PiB", Dimension::Data, 1 << 50, true),
+    unit("KB", Dimension::Data, 1_000, false),
+    unit("MB", Dimension::Data, 1_000_000, false),
+    unit("GB", Dimension::Data, 1_000_000_000, false),
+    unit("TB", Dimension::Data, 1_000_000_000_000, false),
+    unit("ms", Dimension::Time, 1, true),
+    unit("s", Dimension::Time, 1_000, true),
+    unit("min", Dimension::Time, 60_000, true),
+    unit("h", Dimension::Time, 3_600_000, true),
+    unit("d", Dimension::Time, 86_400_000, true),
+    unit("sec", Dimension::Time, 1_000, false),
+    unit("hour", Dimension::Time, 3_600_000, false),
+    unit("day", Dimension::Time, 86_400_000, false),
+];
+
+/// 名前から単位を探す。大文字と小文字は区別する
+pub fn find_unit(name: &str) -> Option<&'static Unit> {
+    UNITS.iter().find(|unit| unit.name == name)
+}
+
+/// value を表示するのにちょうどいい単位。1 以上になる一番大きな単位を選ぶ
+pub fn auto_unit(dimension: Dimension, value: &Number) -> &'static Unit {
+    let value = value.as_f64().abs();
+    let mut units = UNITS
+        .iter()
+        .filter(|unit| unit.auto && unit.dimension == dimension);
+    let smallest = units.next().unwrap_or(&UNITS[0]);
+    units
+        .rfind(|unit| value >= unit.factor as f64)
+        .unwrap_or(smallest)
+}
+
+/// in で変換するときの進数
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Radix {
+    Bin,
+    Oct,
+    Dec,
+    Hex,
+}
+
+impl Radix {
+    /// 0x のような接頭辞をつけて表示する。負の数は -0xff のように符号をつける
+    pub fn format(self, n: &BigInt) -> String {
+        let sign = if n.is_negative() { "-" } else { "" };
+        let n = n.magnitude();
+        match self {
+            Radix::Bin => format!("{}0b{:b}", sign, n),
+            Radix::Oct => format!("{}0o{:o}", sign, n),
+            Radix::Dec => format!("{}{}", sign, n),
+            Radix::Hex => format!("{}0x{:x}", sign, n),
+        }
+    }
+}
+
+/// in の右側に書けるもの
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+    Unit(&'static Unit),
+    Radix(Radix),
+}
+
+pub fn find_target(name: &str) -> Option<Target> {
+    match name {
+        "bin" => Some(Target::Radix(Radix::Bin)),
+        "oct" => Some(Target::Radix(Radix::Oct)),
+        "dec" => Some(Target::Radix(Radix::Dec)),
+        "hex" => Some(Target::Radix(Radix::Hex)),
+        name => find_unit(name).map(Target::Unit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_unit() {
+        assert_eq!(auto_unit(Dimension::Data, &Number::Int(0)).name, "B");
+        assert_eq!(auto_unit(Dimension::Data, &Number::Int(1023)).name, "B");
+        assert_eq!(auto_unit(Dimension::Data, &Number::Int(1024)).name, "KiB");
+        assert_eq!(
+            auto_unit(Dimension::Data, &Number::Int(-(3 << 30))).name,
+            "GiB"
+        );
+        assert_eq!(
+            auto_unit(Dimension::Time, &Number::Int(5_400_000)).name,
+            "h"
+        );
+        assert_eq!(auto_unit(Dimension::Time, &Number::Float(0.5)).name, "ms");
+    }
+
+    #[test]
+    fn test_radix_format() {
+        assert_eq!(Radix::Hex.format(&BigInt::from(255)), "0xff");
+        assert_eq!(Radix::Bin.format(&BigInt::from(10)), "0b1010");
+        assert_eq!(Radix::Oct.format(&BigInt::from(8)), "0o10");
+        assert_eq!(Radix::Hex.format(&BigInt::from(-255)), "-0xff");
+        assert_eq!(
+            Radix::Bin.format(&BigInt::from(i64::MIN)),
+            format!("-0b1{}", "0".repeat(63))
+        );
+        assert_eq!(
+            Radix::Hex.format(&(BigInt::from(1) << 64)),
+            format!("0x1{}", "0".repeat(16))
+        );
+        assert_eq!(Radix::Dec.format(&BigInt::from(-42)), "-42");
+    }
+}
@@ -0,0 +1,237 @@
+use super::number::Number;
+use super::units::{self, Dimension, Target, Unit};
+
+/// 計算結果の表示方法。in で変換した場合だけ Unit か Radix になり、その後に計算すると Auto に戻る
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Auto,
+    Unit(&'static Unit),
+    Radix(units::Radix),
+}
+
+/// 単位つきの数値。単位がある場合 number は基本の単位 (バイト、ミリ秒) での値
+#[derive(Clone, PartialEq, Debug)]
+pub struct Value {
+    pub number: Number,
+    pub dimension: Option<Dimension>,
+    pub format: Format,
+}
+
+impl From<Number> for Value {
+    fn from(number: Number) -> Value {
+        Value {
+            number,
+            dimension: None,
+            format: Format::Auto,
+        }
+    }
+}
+
+impl Value {
+    fn new(number: Number, dimension: Option<Dimension>) -> Value {
+        Value {
+            number,
+            dimension,
+            format: Format::Auto,
+        }
+    }
+
+    /// 1.5GiB のような単位つきの数値
+    pub fn with_unit(number: Number, unit: &Unit) -> Result<Value, String> {
+        let number = number.mul(Number::Int(unit.factor))?;
+        Ok(Value::new(number, Some(unit.dimension)))
+    }
+
+    /// 単位のない数値を取り出す。単位がある場合は what を使ったエラーにする
+    pub fn plain(self, what: &str) -> Result<Number, String> {
+        match self.dimension {
+            None => Ok(self.number),
+            Some(_) => Err(format!("{} には単位のない数値を使ってください", what)),
+        }
+    }
+
+    // 足し算や引き算は同じ種類の単位同士か、単位のない数値同士でだけ計算できる
+    fn same_dimension(
+        self,
+        rhs: Value,
+        op: fn(Number, Number) -> Result<Number, String>,
+    ) -> Result<Value, String> {
+        if self.dimension != rhs.dimension {
+            return Err("単位の種類が違う値は計算できません".to_string());
+        }
+        Ok(Value::new(op(self.number, rhs.number)?, self.dimension))
+    }
+
+    // 単位のない数値同士でだけ使える演算
+    fn both_plain(
+        self,
+        rhs: Value,
+        what: &str,
+        op: fn(Number, Number) -> Result<Number, String>,
+    ) -> Result<Value, String> {
+        Ok(Value::from(op(self.plain(what)?, rhs.plain(what)?)?))
+    }
+
+    pub fn add(self, rhs: Value) -> Result<Value, String> {
+        self.same_dimension(rhs, Number::add)
+    }
+
+    pub fn sub(self, rhs: Value) -> Result<Value, String> {
+        self.same_dimension(rhs, Number::sub)
+    }
+
+    pub fn rem(self, rhs: Value) -> Result<Value, String> {
+        self.same_dimension(rhs, Number::rem)
+    }
+
+    // 単位のある値は単位のない数値とだけ掛けられる
+    pub fn mul(self, rhs: Value) -> Result<Value, String> {
+        let dimension = match (self.dimension, rhs.dimension) {
+            (Some(_), Some(_)) => return Err("単位のある値同士は掛けられません".to_string()),
+            (dimension, None) | (None, dimension) => dimension,
+        };
+        Ok(Value::new(self.number.mul(rhs.number)?, dimension))
+    }
+
+    // 同じ種類の単位同士で割ると単位のない数値になる (1GiB / 1MiB = 1024)
+    pub fn div(self, rhs: Value) -> Result<Value, String> {
+        let dimension = match (self.dimension, rhs.dimension) {
+            (Some(a), Some(b)) if a == b => None,
+            (dimension, None) => dimension,
+            _ => return Err("この単位の組み合わせでは割り算できません".to_string()),
+        };
+        Ok(Value::new(self.number.div(rhs.number)?, dimension))
+    }
+
+    pub fn pow(self, rhs: Value) -> Result<Value, String> {
+        self.both_plain(rhs, "^", Number::pow)
+    }
+
+    pub fn bitand(self, rhs: Value) -> Result<Value, String> {
+        self.both_plain(rhs, "&", Number::bitand)
+    }
+
+    pub fn bitor(self, rhs: Value) -> Result<Value, String> {
+        self.both_plain(rhs, "|", Number::bitor)
+    }
+
+    pub fn shl(self, rhs: Value) -> Result<Value, String> {
+        self.both_plain(rhs, "<<", Number::shl)
+    }
+
+    pub fn shr(self, rhs: Value) -> Result<Value, String> {
+        self.both_plain(rhs, ">>", Number::shr)
+    }
+
+    pub fn neg(self) -> Result<Value, String> {
+        Ok(Value::new(self.number.neg()?, self.dimension))
+    }
+
+    pub fn factorial(self) -> Result<Value, String> {
+        Ok(Value::from(self.plain("!")?.factorial()?))
+    }
+
+    /// in で単位や進数を変換する。値は変わらず、表示方法だけが変わる
+    pub fn convert(self, target: Target) -> Result<Value, String> {
+        let format = match target {
+            Target::Unit(unit) if self.dimension == Some(unit.dimension) => Format::Unit(unit),
+            Target::Unit(unit) => return Err(format!("{} には変換できません", unit.name)),
+            Target::Radix(_) if self.dimension.is_some() => {
+                return Err("進数を変換できるのは単位のない数値だけです".to_string())
+            }
+            Target::Radix(radix) => match (radix, &self.number) {
+                (_, Number::Int(_)) | (_, Number::Big(_)) | (units::Radix::Dec, _) => {
+                    Format::Radix(radix)
+                }
+                _ => return Err("進数を変換できるのは整数だけです".to_string()),
+            },
+        };
+        Ok(Value { format, ..self })
+    }
+
+    /// 単位がある場合は in で指定した単位か、ちょうどいい単位で表示する
+    pub fn format(&self, precision: Option<usize>) -> String {
+        let unit = match (self.format, self.dimension) {
+            (Format::Radix(radix), _) => {
+                return match self.number.to_bigint() {
+                    Some(n) => radix.format(&n),
+                    None => self.number.format(precision),
+                }
+            }
+            (Format::Unit(unit), _) => unit,
+            (Format::Auto, Some(dimension)) => units::auto_unit(dimension, &self.number),
+            (Format::Auto, None) => return self.number.format(precision),
+        };
+        // 単位の factor は正の整数なので割り算は失敗しない
+        let number = self
+            .number
+            .clone()
+            .div(Number::Int(unit.factor))
+            .unwrap_or_else(|_| self.number.clone());
+        format!("{} {}", number.format(precision), unit.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_unit(number: Number, name: &str) -> Value {
+        Value::with_unit(number, units::find_unit(name).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_unit_arithmetic() {
+        let gib = with_unit(Number::Float(1.5), "GiB");
+        let mib = with_unit(Number::Int(512), "MiB");
+        assert_eq!(gib.clone().add(mib.clone()).unwrap().format(None), "2 GiB");
+        assert_eq!(gib.clone().div(mib.clone()).unwrap().format(None), "3");
+        assert_eq!(
+            mib.clone()
+                .mul(Value::from(Number::Int(3)))
+                .unwrap()
+                .format(None),
+            "1.5 GiB"
+        );
+        assert_eq!(
+            gib.clone().add(with_unit(Number::Int(1), "s")),
+            Err("単位の種類が違う値は計算できません".to_string())
+        );
+        assert_eq!(
+            gib.clone().add(Value::from(Number::Int(1))),
+            Err("単位の種類が違う値は計算できません".to_string())
+        );
+        assert_eq!(
+            gib.mul(mib),
+            Err("単位のある値同士は掛けられません".to_string())
+        );
+    }
+
+    #[test]
+    fn test_convert() {
+        let minutes = with_unit(Number::Int(90), "min");
+        assert_eq!(minutes.format(None), "1.5 h");
+        let target = units::find_target("s").unwrap();
+        assert_eq!(
+            minutes.clone().convert(target).unwrap().format(None),
+            "5400 s"
+        );
+
+        let hex = units::find_target("hex").unwrap();
+        assert_eq!(
+            Value::from(Number::Int(255))
+                .convert(hex)
+                .unwrap()
+                .format(None),
+            "0xff"
+        );
+        assert_eq!(
+            Value::from(Number::Float(1.5)).convert(hex),
+            Err("進数を変換できるのは整数だけです".to_string())
+        );
+        assert_eq!(
+            minutes.convert(units::find_target("GiB").unwrap()),
+            Err("GiB には変換できません".to_string())
+        );
+    }
+}
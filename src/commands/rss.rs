@@ -7,8 +7,21 @@ use serenity::model::prelude::interaction::application_command::{
 };
 
 use serenity::prelude::Context;
+use tracing::error;
 
-use crate::utils::get_db_channel::get_db_channel;
+use crate::store::get_store;
+use crate::store::record::RssLink;
+use crate::url::url::Url;
+use crate::utils::fetch_rss_feed::resolve_feed_url;
+
+// 表記揺れがあっても同じフィードとして扱えるように正規化した URL を返す。
+// 以前から登録されている URL は parse できないこともあるので、その場合はそのまま使う。
+fn canonical_link(link: &str) -> String {
+    match Url::parse(link) {
+        Ok(url) => url.normalize().to_string(),
+        Err(_) => link.to_string(),
+    }
+}
 
 pub async fn run(options: &[CommandDataOption], ctx: &Context) -> String {
     let operation = match options.iter().find(|option| option.name == "operation") {
@@ -33,80 +46,96 @@ pub async fn run(options: &[CommandDataOption], ctx: &Context) -> String {
         None => "",
     };
 
-    let db_channel = match get_db_channel(ctx).await {
-        Ok(db_channel) => db_channel,
-        Err(why) => return why.to_string(),
+    let store = match get_store(ctx).await {
+        Ok(store) => store,
+        Err(why) => {
+            error!("failed to open store: {}", why);
+            return "DBチャンネルが見つかりません。".to_string();
+        }
     };
 
-    let messages = match db_channel
-        .messages(&ctx.http, |retriever| retriever.limit(100))
-        .await
-    {
-        Ok(messages) => messages
-            .into_iter()
-            .filter(|message| message.content.starts_with("rss_link"))
-            .collect::<Vec<_>>(),
-        Err(_) => return "リンクの取得に失敗しました".to_string(),
+    let links = match store.list::<RssLink>().await {
+        Ok(links) => links,
+        Err(why) => {
+            error!("failed to load rss links: {}", why);
+            return "リンクの取得に失敗しました".to_string();
+        }
     };
 
     match operation.as_str() {
         "add" => {
+            if let Err(why) = Url::parse(link) {
+                return format!("{} は正しい URL ではありません: {}", link, why);
+            }
+            // リダイレクト先の正規の URL で登録する
+            let input = link;
+            let link = canonical_link(&resolve_feed_url(input).await);
+
             // 重複チェック
-            if messages
+            if links
                 .iter()
-                .any(|x| x.content.split_whitespace().nth(1).unwrap() == link)
+                .any(|stored| canonical_link(&stored.record.link) == link)
             {
                 return format!("{} は既に登録されています。", link);
             }
 
-            let _ = match db_channel
-                .id
-                .say(&ctx.http, format!("rss_link {}", link))
-                .await
-            {
-                Ok(_) => "",
-                Err(_) => return "リンクの登録に失敗しました。".to_string(),
-            };
+            let record = RssLink { link };
+            if let Err(why) = store.insert_record(&record).await {
+                error!("failed to insert rss link: {}", why);
+                return "リンクの登録に失敗しました。".to_string();
+            }
 
-            return format!("{} を追加しました。", link);
+            if record.link == canonical_link(input) {
+                format!("{} を追加しました。", record.link)
+            } else {
+                format!(
+                    "{} を追加しました。({} のリダイレクト先です)",
+                    record.link, input
+                )
+            }
         }
         "rm" => {
-            match messages
-                .iter()
-                .position(|x| x.content.split_whitespace().nth(1).unwrap() == link)
-            {
-                Some(index) => {
-                    let link_id = messages[index].id;
-                    match db_channel.id.delete_message(&ctx.http, link_id).await {
-                        Ok(_) => (),
-                        Err(_) => return "リンクの削除に失敗しました".to_string(),
-                    }
-                    return format!("{} を削除しました。", link);
+            let find = |canonical: &str| {
+                links
+                    .iter()
+                    .find(|stored| canonical_link(&stored.record.link) == canonical)
+            };
+            // add ではリダイレクト先を登録しているので、見つからなければリダイレクト先でも探す
+            let found = match find(&canonical_link(link)) {
+                Some(stored) => Some(stored),
+                None if Url::parse(link).is_ok() => {
+                    find(&canonical_link(&resolve_feed_url(link).await))
                 }
-                None => {
-                    return format!("{} は見つかりませんでした。", link);
+                None => None,
+            };
+            match found {
+                Some(stored) => {
+                    if let Err(why) = store.delete_record::<RssLink>(stored.id).await {
+                        error!("failed to delete rss link: {}", why);
+                        return "リンクの削除に失敗しました".to_string();
+                    }
+                    format!("{} を削除しました。", link)
                 }
+                None => format!("{} は見つかりませんでした。", link),
             }
         }
         "ls" => {
-            if messages.is_empty() {
-                return "RSSが登録されていません。".to_string();
+            if links.is_empty() {
+                "RSSが登録されていません。".to_string()
             } else {
-                return format!(
+                format!(
                     "rss list は以下の通りです:
 - {}",
-                    messages
+                    links
                         .iter()
-                        .map(|x| format!("{}", x.content.split_whitespace().nth(1).unwrap()))
+                        .map(|stored| stored.record.link.as_str())
                         .collect::<Vec<_>>()
                         .join("\n- ")
-                );
+                )
             }
         }
-        _ => {}
+        _ => "".to_string(),
     }
-
-    "".to_string()
 }
 
 pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
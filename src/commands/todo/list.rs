@@ -0,0 +1,458 @@
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::message_component::MessageComponentInteraction;
+use serenity::model::prelude::interaction::InteractionResponseType;
+use serenity::prelude::Context;
+use tracing::{error, warn};
+
+use super::{format_todo, scope_option, status_option, user_option, visible_todos};
+use crate::store::record::{Todo, TodoScope, TodoStatus};
+use crate::store::Stored;
+
+// 1 ページに表示する todo の数。完了ボタンを 1 行に並べられる数にする
+const PAGE_SIZE: usize = 5;
+// メニューに表示するタイトルの長さ
+const LABEL_LENGTH: usize = 50;
+// 一覧に表示するタイトルの長さ。embed の description は 4096 文字までなので、5 件並べても収まるようにする
+const TITLE_LENGTH: usize = 300;
+
+/// ls の絞り込み
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+struct Filter {
+    assignee: Option<u64>,
+    status: Option<TodoStatus>,
+    scope: Option<TodoScope>,
+}
+
+impl Filter {
+    fn matches(&self, todo: &Todo) -> bool {
+        self.assignee
+            .is_none_or(|assignee| todo.assignee == Some(assignee))
+            && self.status.is_none_or(|status| todo.status == status)
+            && self.scope.is_none_or(|scope| todo.scope == scope)
+    }
+}
+
+/// 表示している一覧。ボタンを押したときに同じ一覧を作れるように custom_id に入れる。
+/// owner は ls を実行した人で、一覧は owner から見える todo なので owner だけが操作できる
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct List {
+    owner: u64,
+    filter: Filter,
+    page: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    /// page のページを表示する
+    Page,
+    /// todo を完了にする
+    Done(u64),
+    /// 選んだ todo のステータスを変える。todo とステータスはメニューの値
+    Status,
+}
+
+fn format_option<T>(value: Option<T>, format: impl Fn(T) -> String) -> String {
+    value.map_or("-".to_string(), format)
+}
+
+fn parse_option<T>(text: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Option<T>> {
+    if text == "-" {
+        Some(None)
+    } else {
+        parse(text).map(Some)
+    }
+}
+
+fn format_scope(scope: TodoScope) -> String {
+    match scope {
+        TodoScope::Global => "a".to_string(),
+        TodoScope::User(id) => format!("u{}", id),
+        TodoScope::Channel(id) => format!("c{}", id),
+        TodoScope::Guild(id) => format!("g{}", id),
+    }
+}
+
+fn parse_scope(text: &str) -> Option<TodoScope> {
+    if text == "a" {
+        return Some(TodoScope::Global);
+    }
+    let id = text.get(1..)?.parse().ok()?;
+    match text.chars().next()? {
+        'u' => Some(TodoScope::User(id)),
+        'c' => Some(TodoScope::Channel(id)),
+        'g' => Some(TodoScope::Guild(id)),
+        _ => None,
+    }
+}
+
+// todo:<owner>:<assignee>:<status>:<scope>:<page>:<action>。Discord の custom_id は 100 文字まで
+fn custom_id(list: List, action: Action) -> String {
+    let action = match action {
+        Action::Page => "page".to_string(),
+        Action::Done(id) => format!("done{}", id),
+        Action::Status => "status".to_string(),
+    };
+    format!(
+        "todo:{}:{}:{}:{}:{}:{}",
+        list.owner,
+        format_option(list.filter.assignee, |id| id.to_string()),
+        format_option(list.filter.status, |status| status.name().to_string()),
+        format_option(list.filter.scope, format_scope),
+        list.page,
+        action
+    )
+}
+
+fn parse_custom_id(custom_id: &str) -> Option<(List, Action)> {
+    let parts: Vec<&str> = custom_id.split(':').collect();
+    if parts.len() != 7 || parts[0] != "todo" {
+        return None;
+    }
+    let list = List {
+        owner: parts[1].parse().ok()?,
+        filter: Filter {
+            assignee: parse_option(parts[2], |id| id.parse().ok())?,
+            status: parse_option(parts[3], TodoStatus::parse)?,
+            scope: parse_option(parts[4], parse_scope)?,
+        },
+        page: parts[5].parse().ok()?,
+    };
+    let action = match parts[6] {
+        "page" => Action::Page,
+        "status" => Action::Status,
+        action => Action::Done(action.strip_prefix("done")?.parse().ok()?),
+    };
+    Some((list, action))
+}
+
+/// ページ数と、範囲に収めたページを返す
+fn pages(length: usize, page: usize) -> (usize, usize) {
+    let pages = length.div_ceil(PAGE_SIZE).max(1);
+    (pages, page.min(pages - 1))
+}
+
+// length 文字より長ければ切り詰めて … をつける
+pub(crate) fn truncate(text: &str, length: usize) -> String {
+    if text.chars().count() <= length {
+        return text.to_string();
+    }
+    let truncated: String = text.chars().take(length).collect();
+    format!("{}…", truncated)
+}
+
+fn label(todo: &Todo, status: TodoStatus) -> String {
+    format!(
+        "{} {} → {}",
+        todo.id,
+        truncate(&todo.title, LABEL_LENGTH),
+        status.name()
+    )
+}
+
+/// 一覧の description
+fn description(todos: &[&Todo]) -> String {
+    todos
+        .iter()
+        .map(|todo| {
+            let todo = Todo {
+                title: truncate(&todo.title, TITLE_LENGTH),
+                ..(*todo).clone()
+            };
+            format!("・{}", format_todo(&todo))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 一覧の embed とボタンを作る
+fn render(todos: &[Stored<Todo>], list: List) -> (CreateEmbed, CreateComponents) {
+    let todos: Vec<&Todo> = todos
+        .iter()
+        .map(|todo| &todo.record)
+        .filter(|todo| list.filter.matches(todo))
+        .collect();
+    let (pages, page) = pages(todos.len(), list.page);
+    let list = List { page, ..list };
+    let shown: Vec<&Todo> = todos
+        .iter()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .copied()
+        .collect();
+
+    let mut embed = CreateEmbed::default();
+    embed.title("TODOリスト");
+    if shown.is_empty() {
+        embed.description("TODOリストには何もありません。");
+    } else {
+        embed.description(description(&shown));
+    }
+    embed.footer(|footer| footer.text(format!("{} / {} ページ", page + 1, pages)));
+
+    let mut components = CreateComponents::default();
+    if !shown.is_empty() {
+        components.create_action_row(|row| {
+            for todo in &shown {
+                row.create_button(|button| {
+                    button
+                        .custom_id(custom_id(list, Action::Done(todo.id)))
+                        .label(format!("{} 完了", todo.id))
+                        .style(ButtonStyle::Success)
+                        .disabled(todo.status == TodoStatus::Done)
+                });
+            }
+            row
+        });
+        components.create_action_row(|row| {
+            row.create_select_menu(|menu| {
+                menu.custom_id(custom_id(list, Action::Status))
+                    .placeholder("ステータスを変更")
+                    .options(|options| {
+                        for todo in &shown {
+                            for status in TodoStatus::ALL {
+                                if status != todo.status {
+                                    options.create_option(|option| {
+                                        option.label(label(todo, status)).value(format!(
+                                            "{}:{}",
+                                            todo.id,
+                                            status.name()
+                                        ))
+                                    });
+                                }
+                            }
+                        }
+                        options
+                    })
+            })
+        });
+    }
+    if pages > 1 {
+        components.create_action_row(|row| {
+            row.create_button(|button| {
+                button
+                    .custom_id(custom_id(
+                        List {
+                            page: page.saturating_sub(1),
+                            ..list
+                        },
+                        Action::Page,
+                    ))
+                    .label("前へ")
+                    .style(ButtonStyle::Secondary)
+                    .disabled(page == 0)
+            })
+            .create_button(|button| {
+                button
+                    .custom_id(custom_id(
+                        List {
+                            page: (page + 1).min(pages - 1),
+                            ..list
+                        },
+                        Action::Page,
+                    ))
+                    .label("次へ")
+                    .style(ButtonStyle::Secondary)
+                    .disabled(page + 1 == pages)
+            })
+        });
+    }
+    (embed, components)
+}
+
+/// /todo ls の一覧を作る
+pub async fn show(
+    command: &ApplicationCommandInteraction,
+    ctx: &Context,
+) -> Result<(CreateEmbed, CreateComponents), String> {
+    let options = &command.data.options;
+    let filter = Filter {
+        assignee: user_option(options, "assignee").map(|user| user.0),
+        status: status_option(options)?,
+        scope: scope_option(command)?,
+    };
+    let list = List {
+        owner: command.user.id.0,
+        filter,
+        page: 0,
+    };
+    let guild = command.guild_id.map(|guild| guild.0);
+    let (_, todos, _) = visible_todos(ctx, list.owner, command.channel_id.0, guild).await?;
+    Ok(render(&todos, list))
+}
+
+/// ボタンやメニューの操作を反映した一覧を返す
+async fn update(
+    component: &MessageComponentInteraction,
+    ctx: &Context,
+    list: List,
+    action: Action,
+) -> Result<(CreateEmbed, CreateComponents), String> {
+    // 他の人が押すと owner の個人の todo を表示してしまう
+    if component.user.id.0 != list.owner {
+        return Err("この一覧を操作できるのは ls を実行した人だけです".to_string());
+    }
+    let channel = component.channel_id.0;
+    let guild = component.guild_id.map(|guild| guild.0);
+    let (store, mut todos, _) = visible_todos(ctx, list.owner, channel, guild).await?;
+
+    let change = match action {
+        Action::Page => None,
+        Action::Done(id) => Some((id, TodoStatus::Done)),
+        Action::Status => {
+            let value = component
+                .data
+                .values
+                .first()
+                .ok_or("ステータスが選ばれていません")?;
+            let (id, status) = value
+                .split_once(':')
+                .and_then(|(id, status)| Some((id.parse().ok()?, TodoStatus::parse(status)?)))
+                .ok_or("ステータスが不正です")?;
+            Some((id, status))
+        }
+    };
+
+    if let Some((id, status)) = change {
+        let todo = todos
+            .iter_mut()
+            .find(|todo| todo.record.id == id)
+            .ok_or(format!("{} は存在しません。", id))?;
+        todo.record.status = status;
+        if let Err(why) = store.update_record(todo.id, &todo.record).await {
+            error!("failed to update todo: {}", why);
+            return Err("メッセージの編集に失敗しました".to_string());
+        }
+    }
+    Ok(render(&todos, list))
+}
+
+/// todo の一覧のボタンとメニューが押されたときに呼ばれる
+pub async fn handle_component(component: &MessageComponentInteraction, ctx: &Context) {
+    let (list, action) = match parse_custom_id(&component.data.custom_id) {
+        Some(parsed) => parsed,
+        None => {
+            warn!("invalid todo component: {}", component.data.custom_id);
+            return;
+        }
+    };
+    let reply = update(component, ctx, list, action).await;
+
+    if let Err(why) = component
+        .create_interaction_response(&ctx.http, |response| match reply {
+            Ok((embed, components)) => response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message.set_embed(embed).set_components(components)
+                }),
+            // 一覧はそのままにして、押した人にだけ知らせる
+            Err(content) => response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| message.content(content).ephemeral(true)),
+        })
+        .await
+    {
+        error!("failed to create interaction response: {:?}", why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_id() {
+        let list = List {
+            owner: 889012300705591307,
+            filter: Filter {
+                assignee: Some(1208611584964825099),
+                status: Some(TodoStatus::Doing),
+                scope: Some(TodoScope::Channel(1208611584964825099)),
+            },
+            page: 3,
+        };
+        for action in [Action::Page, Action::Done(12), Action::Status] {
+            let id = custom_id(list, action);
+            assert!(id.len() <= 100);
+            assert_eq!(parse_custom_id(&id), Some((list, action)));
+        }
+
+        let list = List {
+            owner: 1,
+            filter: Filter::default(),
+            page: 0,
+        };
+        assert_eq!(custom_id(list, Action::Done(2)), "todo:1:-:-:-:0:done2");
+        assert_eq!(
+            parse_custom_id("todo:1:-:-:a:0:page"),
+            Some((
+                List {
+                    filter: Filter {
+                        scope: Some(TodoScope::Global),
+                        ..Filter::default()
+                    },
+                    ..list
+                },
+                Action::Page
+            ))
+        );
+        assert_eq!(parse_custom_id("todo:1:-:-:-:0:remove"), None);
+        assert_eq!(parse_custom_id("todo:1:-:finished:-:0:page"), None);
+        assert_eq!(parse_custom_id("rss:1"), None);
+    }
+
+    #[test]
+    fn test_description() {
+        // スラッシュコマンドでは 6000 文字まで入力できるが、5 件並べても embed に収める
+        let todos: Vec<Todo> = (1..=PAGE_SIZE as u64)
+            .map(|id| Todo {
+                assignee: Some(1208611584964825099),
+                scope: TodoScope::Channel(1208611584964825099),
+                ..Todo::new(id, &"長".repeat(6000))
+            })
+            .collect();
+        let todos: Vec<&Todo> = todos.iter().collect();
+        assert!(description(&todos).chars().count() <= 4096);
+
+        let short = Todo::new(1, "release");
+        assert_eq!(description(&[&short]), "・1 [open] release");
+        assert_eq!(truncate("abcdef", 3), "abc…");
+    }
+
+    #[test]
+    fn test_pages() {
+        assert_eq!(pages(0, 0), (1, 0));
+        assert_eq!(pages(5, 0), (1, 0));
+        assert_eq!(pages(6, 1), (2, 1));
+        // 完了にして todo が減った場合は最後のページにする
+        assert_eq!(pages(6, 4), (2, 1));
+    }
+
+    #[test]
+    fn test_filter() {
+        let todo = Todo {
+            assignee: Some(1),
+            status: TodoStatus::Doing,
+            scope: TodoScope::Guild(3),
+            ..Todo::new(1, "release")
+        };
+        assert!(Filter::default().matches(&todo));
+        assert!(Filter {
+            assignee: Some(1),
+            status: Some(TodoStatus::Doing),
+            scope: Some(TodoScope::Guild(3)),
+        }
+        .matches(&todo));
+        assert!(!Filter {
+            status: Some(TodoStatus::Open),
+            ..Filter::default()
+        }
+        .matches(&todo));
+        assert!(!Filter {
+            assignee: Some(2),
+            ..Filter::default()
+        }
+        .matches(&todo));
+    }
+}
@@ -0,0 +1,441 @@
+use std::sync::Arc;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+use serenity::model::prelude::interaction::InteractionResponseType;
+use serenity::model::prelude::UserId;
+
+use serenity::prelude::Context;
+use tracing::error;
+
+use crate::store::record::{Todo, TodoScope, TodoStatus};
+use crate::store::{get_store, Store, Stored};
+
+pub mod list;
+
+// 返信に入れるタイトルの長さ。Discord のメッセージは 2000 文字まで
+const TITLE_LENGTH: usize = 300;
+
+fn string_option<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a str> {
+    match options.iter().find(|option| option.name == name)?.resolved {
+        Some(CommandDataOptionValue::String(ref text)) => Some(text),
+        _ => None,
+    }
+}
+
+fn user_option(options: &[CommandDataOption], name: &str) -> Option<UserId> {
+    match options.iter().find(|option| option.name == name)?.resolved {
+        Some(CommandDataOptionValue::User(ref user, _)) => Some(user.id),
+        _ => None,
+    }
+}
+
+/// 期限を読む。日付だけの場合はその日の終わりまで
+fn parse_due(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    if let Ok(due) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M") {
+        return Some(due);
+    }
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    Some(date.and_time(NaiveTime::from_hms_opt(23, 59, 0)?))
+}
+
+/// 一覧で表示する 1 行
+fn format_todo(todo: &Todo) -> String {
+    let mut line = format!("{} [{}] {}", todo.id, todo.status.name(), todo.title);
+    if let Some(due) = todo.due {
+        line += &format!(" 期限: {}", due.format("%Y-%m-%d %H:%M"));
+    }
+    if let Some(assignee) = todo.assignee {
+        line += &format!(" 担当: <@{}>", assignee);
+    }
+    match todo.scope {
+        TodoScope::User(_) => line += " (個人)",
+        TodoScope::Channel(channel) => line += &format!(" (<#{}>)", channel),
+        TodoScope::Guild(_) | TodoScope::Global => {}
+    }
+    line
+}
+
+/// add と edit の返信に入れる 1 行。長いタイトルは切り詰める
+fn format_reply(todo: &Todo) -> String {
+    format_todo(&Todo {
+        title: list::truncate(&todo.title, TITLE_LENGTH),
+        ..todo.clone()
+    })
+}
+
+/// scope オプションの名前を実行した場所の範囲にする
+fn parse_scope(name: &str, command: &ApplicationCommandInteraction) -> Option<TodoScope> {
+    match name {
+        "user" => Some(TodoScope::User(command.user.id.0)),
+        "channel" => Some(TodoScope::Channel(command.channel_id.0)),
+        "guild" => command.guild_id.map(|guild| TodoScope::Guild(guild.0)),
+        _ => None,
+    }
+}
+
+fn status_option(options: &[CommandDataOption]) -> Result<Option<TodoStatus>, String> {
+    match string_option(options, "status") {
+        Some(name) => match TodoStatus::parse(name) {
+            Some(status) => Ok(Some(status)),
+            None => Err(format!("ステータス {} は存在しません", name)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn scope_option(command: &ApplicationCommandInteraction) -> Result<Option<TodoScope>, String> {
+    match string_option(&command.data.options, "scope") {
+        Some(name) => match parse_scope(name, command) {
+            Some(scope) => Ok(Some(scope)),
+            None => Err(format!("範囲 {} はここでは使えません", name)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// todo の範囲を scope に変えてよいか。共有の todo を他の人が個人の todo にして隠せないように、
+/// 範囲を変えられるのは作った人だけにする
+fn can_change_scope(todo: &Todo, user: u64, scope: Option<TodoScope>) -> bool {
+    match scope {
+        Some(scope) if scope != todo.scope => todo.created_by == Some(user),
+        _ => true,
+    }
+}
+
+/// 操作する todo。id を指定した場合は ID だけで探す。
+/// edit では message が新しいタイトルになるので、他の todo の ID やタイトルと一致しても選ばない
+fn find_target<'a>(todos: &'a [Stored<Todo>], message: &str, id: &str) -> Option<&'a Stored<Todo>> {
+    if !id.is_empty() {
+        return todos.iter().find(|todo| todo.record.id.to_string() == id);
+    }
+    // ID かタイトルのどちらかが一致する todo
+    todos.iter().find(|todo| {
+        todo.record.id.to_string() == message
+            || (!todo.record.title.is_empty() && todo.record.title == message)
+    })
+}
+
+/// user が channel から見られる todo と、次に追加する todo の ID を返す
+async fn visible_todos(
+    ctx: &Context,
+    user: u64,
+    channel: u64,
+    guild: Option<u64>,
+) -> Result<(Arc<dyn Store>, Vec<Stored<Todo>>, u64), String> {
+    let store = match get_store(ctx).await {
+        Ok(store) => store,
+        Err(why) => {
+            error!("failed to open store: {}", why);
+            return Err("DBチャンネルが見つかりません。".to_string());
+        }
+    };
+
+    let todos = match store.list::<Todo>().await {
+        Ok(todos) => todos,
+        Err(why) => {
+            error!("failed to load todos: {}", why);
+            return Err("メッセージの取得に失敗しました".to_string());
+        }
+    };
+
+    // ID は見えない todo も含めて振る
+    let next_id = todos.iter().map(|todo| todo.record.id).max().unwrap_or(0) + 1;
+    let todos = todos
+        .into_iter()
+        .filter(|todo| todo.record.scope.is_visible(user, channel, guild))
+        .collect();
+    Ok((store, todos, next_id))
+}
+
+pub async fn run(command: &ApplicationCommandInteraction, ctx: &Context) {
+    // ls はボタンで操作できる一覧を返す。それ以外の操作と一覧が作れなかった場合はメッセージを返す。
+    // 一覧と個人の todo は他の人に見えないように、実行した人にだけ表示する
+    let mut private = false;
+    let reply = match string_option(&command.data.options, "operation") {
+        Some("ls") => {
+            private = true;
+            list::show(command, ctx).await
+        }
+        _ => Err(todo(command, ctx, &mut private).await),
+    };
+
+    if let Err(why) = command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    match reply {
+                        Ok((embed, components)) => {
+                            message.set_embed(embed).set_components(components)
+                        }
+                        Err(content) => message.content(content),
+                    }
+                    .ephemeral(private)
+                })
+        })
+        .await
+    {
+        error!("failed to create interaction response: {:?}", why);
+    }
+}
+
+/// private は返すメッセージに個人の todo が含まれる場合に true にする
+async fn todo(
+    command: &ApplicationCommandInteraction,
+    ctx: &Context,
+    private: &mut bool,
+) -> String {
+    let options = &command.data.options;
+    let operation = match string_option(options, "operation") {
+        Some(operation) => operation,
+        None => return "オペレーションが不正です".to_string(),
+    };
+    let todo_message = string_option(options, "message").unwrap_or("");
+    let todo_id = string_option(options, "id").unwrap_or("");
+    let assignee = user_option(options, "assignee");
+
+    let due = match string_option(options, "due") {
+        Some(text) => match parse_due(text) {
+            Some(due) => Some(due),
+            None => {
+                return format!(
+                    "期限 {} は YYYY-MM-DD か YYYY-MM-DD HH:MM の形式で指定してください",
+                    text
+                )
+            }
+        },
+        None => None,
+    };
+    let status = match status_option(options) {
+        Ok(status) => status,
+        Err(message) => return message,
+    };
+    let scope = match scope_option(command) {
+        Ok(scope) => scope,
+        Err(message) => return message,
+    };
+
+    // 実行した人がここから見られる todo だけを扱う
+    let user = command.user.id.0;
+    let channel = command.channel_id.0;
+    let guild = command.guild_id.map(|guild| guild.0);
+    let (store, todos, next_id) = match visible_todos(ctx, user, channel, guild).await {
+        Ok(loaded) => loaded,
+        Err(message) => return message,
+    };
+
+    let target = find_target(&todos, todo_message, todo_id);
+
+    match operation {
+        "add" => {
+            if todo_message.is_empty() {
+                return "message にタイトルを指定してください".to_string();
+            }
+            // 範囲を指定しなければサーバー全体、DM では個人の todo にする
+            let scope = scope.unwrap_or(match guild {
+                Some(guild) => TodoScope::Guild(guild),
+                None => TodoScope::User(user),
+            });
+            let todo = Todo {
+                due,
+                assignee: assignee.map(|user| user.0),
+                status: status.unwrap_or_default(),
+                created_by: Some(user),
+                scope,
+                channel: Some(channel),
+                ..Todo::new(next_id, todo_message)
+            };
+            *private = matches!(todo.scope, TodoScope::User(_));
+            if let Err(why) = store.insert_record(&todo).await {
+                error!("failed to insert todo: {}", why);
+                return "メッセージの送信に失敗しました".to_string();
+            }
+            format!("{} を追加しました。", format_reply(&todo))
+        }
+        "rm" => match target {
+            Some(todo) => {
+                *private = matches!(todo.record.scope, TodoScope::User(_));
+                if let Err(why) = store.delete_record::<Todo>(todo.id).await {
+                    error!("failed to delete todo: {}", why);
+                    return "メッセージの削除に失敗しました".to_string();
+                }
+                format!(
+                    "{}: {} を削除しました。",
+                    todo.record.id,
+                    list::truncate(&todo.record.title, TITLE_LENGTH)
+                )
+            }
+            None => format!(
+                "{} は見つかりませんでした。",
+                list::truncate(todo_message, TITLE_LENGTH)
+            ),
+        },
+        "edit" => match target {
+            Some(todo) => {
+                if !can_change_scope(&todo.record, user, scope) {
+                    return "範囲を変更できるのは todo を作った人だけです".to_string();
+                }
+                // 指定された項目だけ変更する。message は ID で指定した場合だけタイトルにする
+                let title = if !todo_id.is_empty() && !todo_message.is_empty() {
+                    todo_message.to_string()
+                } else {
+                    todo.record.title.clone()
+                };
+                // 期限を変えたら通知を送り直す
+                let reminded = match due {
+                    Some(due) if Some(due) != todo.record.due => Vec::new(),
+                    _ => todo.record.reminded.clone(),
+                };
+                let edited = Todo {
+                    title,
+                    due: due.or(todo.record.due),
+                    reminded,
+                    assignee: assignee.map(|user| user.0).or(todo.record.assignee),
+                    status: status.unwrap_or(todo.record.status),
+                    scope: scope.unwrap_or(todo.record.scope),
+                    ..todo.record.clone()
+                };
+                *private = [todo.record.scope, edited.scope]
+                    .iter()
+                    .any(|scope| matches!(scope, TodoScope::User(_)));
+                if let Err(why) = store.update_record(todo.id, &edited).await {
+                    error!("failed to update todo: {}", why);
+                    return "メッセージの編集に失敗しました".to_string();
+                }
+                format!("{} に編集しました。", format_reply(&edited))
+            }
+            None => format!("{} は存在しません。", list::truncate(todo_id, TITLE_LENGTH)),
+        },
+        _ => "".to_string(),
+    }
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("todo")
+        .description("todo list を管理します")
+        .create_option(|option| {
+            option
+                .name("operation")
+                .kind(CommandOptionType::String)
+                .description("オペレーション")
+                .add_string_choice("add", "add")
+                .add_string_choice("rm", "rm")
+                .add_string_choice("ls", "ls")
+                .add_string_choice("edit", "edit")
+                .required(true)
+        })
+        .create_option(|option| {
+            option
+                .name("message")
+                .kind(CommandOptionType::String)
+                .description("タイトル")
+                .required(false)
+        })
+        .create_option(|option| {
+            option
+                .name("id")
+                .kind(CommandOptionType::String)
+                .description("ID")
+                .required(false)
+        })
+        .create_option(|option| {
+            option
+                .name("due")
+                .kind(CommandOptionType::String)
+                .description("期限 (YYYY-MM-DD か YYYY-MM-DD HH:MM)")
+                .required(false)
+        })
+        .create_option(|option| {
+            option
+                .name("assignee")
+                .kind(CommandOptionType::User)
+                .description("担当者。ls では担当者で絞り込みます")
+                .required(false)
+        })
+        .create_option(|option| {
+            let option = option
+                .name("status")
+                .kind(CommandOptionType::String)
+                .description("ステータス。ls ではステータスで絞り込みます")
+                .required(false);
+            for status in TodoStatus::ALL {
+                option.add_string_choice(status.name(), status.name());
+            }
+            option
+        })
+        .create_option(|option| {
+            option
+                .name("scope")
+                .kind(CommandOptionType::String)
+                .description("範囲。ls では範囲で絞り込みます")
+                .add_string_choice("user", "user")
+                .add_string_choice("channel", "channel")
+                .add_string_choice("guild", "guild")
+                .required(false)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_due() {
+        assert_eq!(
+            parse_due("2024-01-02 18:30").map(|due| due.to_string()),
+            Some("2024-01-02 18:30:00".to_string())
+        );
+        assert_eq!(
+            parse_due("2024-01-02").map(|due| due.to_string()),
+            Some("2024-01-02 23:59:00".to_string())
+        );
+        assert_eq!(parse_due("2024/01/02"), None);
+        assert_eq!(parse_due("tomorrow"), None);
+    }
+
+    #[test]
+    fn test_format_reply() {
+        let todo = Todo::new(1, &"a".repeat(6000));
+        assert!(format_reply(&todo).chars().count() < 2000);
+        assert_eq!(format_reply(&Todo::new(1, "release")), "1 [open] release");
+    }
+
+    #[test]
+    fn test_find_target() {
+        let todos: Vec<Stored<Todo>> = [(2, "1"), (1, "release")]
+            .iter()
+            .map(|(id, title)| Stored {
+                id: *id,
+                record: Todo::new(*id, title),
+            })
+            .collect();
+        let found = |message, id| find_target(&todos, message, id).map(|todo| todo.record.id);
+        assert_eq!(found("release", ""), Some(1));
+        assert_eq!(found("2", ""), Some(2));
+        // id を指定した場合は message が他の todo と一致しても ID で選ぶ
+        assert_eq!(found("1", "1"), Some(1));
+        assert_eq!(found("release", "2"), Some(2));
+        assert_eq!(found("release", "3"), None);
+    }
+
+    #[test]
+    fn test_can_change_scope() {
+        let todo = Todo {
+            created_by: Some(1),
+            scope: TodoScope::Guild(10),
+            ..Todo::new(1, "release")
+        };
+        assert!(can_change_scope(&todo, 1, Some(TodoScope::User(1))));
+        // 作った人以外は範囲を変えられない
+        assert!(!can_change_scope(&todo, 2, Some(TodoScope::User(2))));
+        assert!(can_change_scope(&todo, 2, Some(TodoScope::Guild(10))));
+        assert!(can_change_scope(&todo, 2, None));
+    }
+}
@@ -4,7 +4,7 @@ use serenity::model::prelude::interaction::application_command::{
     CommandDataOption, CommandDataOptionValue,
 };
 
-use crate::utils::percent_decode::percent_decode;
+use crate::utils::percent_decode::percent_decode_lossy;
 use crate::utils::wikipedia_search::wikipedia_search;
 
 pub async fn run(options: &[CommandDataOption]) -> String {
@@ -30,7 +30,7 @@ pub async fn run(options: &[CommandDataOption]) -> String {
 {}
 https://ja.wikipedia.org/wiki/{}",
         search_text,
-        percent_decode(text.as_str()),
+        percent_decode_lossy(text.as_str()),
         json.query.pages.iter().next().unwrap().1.extract,
         json.query.pages.iter().next().unwrap().1.title
     );
@@ -2,11 +2,21 @@ use serenity::{
     client::Context,
     model::application::interaction::{Interaction, InteractionResponseType},
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::commands;
 
 pub async fn interaction_create(ctx: Context, interaction: Interaction) {
+    // ボタンやメニューの custom_id は <コマンド名>:... にしている
+    if let Interaction::MessageComponent(component) = interaction {
+        info!("called component: {:?}", component.data.custom_id);
+        match component.data.custom_id.split(':').next() {
+            Some("todo") => commands::todo::list::handle_component(&component, &ctx).await,
+            _ => warn!("unknown component: {:?}", component.data.custom_id),
+        }
+        return;
+    }
+
     if let Interaction::ApplicationCommand(command) = interaction {
         info!("called command: {:?}", command.data.name);
         let content = match command.data.name.as_str() {
@@ -14,8 +24,10 @@ pub async fn interaction_create(ctx: Context, interaction: Interaction) {
             "friday" => commands::friday::run(&command.data.options),
             "cat" => commands::cat::run(&command.data.options),
             "wiki" => commands::wiki::run(&command.data.options).await,
-            "eval" => commands::eval::run(&command.data.options).await,
-            "todo" => commands::todo::run(&command.data.options, &ctx).await,
+            // 結果が長い場合はファイルを添付するので eval は自分で返信する
+            "eval" => return commands::eval::run(&command, &ctx).await,
+            // ls は一覧にボタンをつけるので todo も自分で返信する
+            "todo" => return commands::todo::run(&command, &ctx).await,
             "image" => commands::image::run(&command.data.options).await,
             "github_trend" => commands::github_trend::run(&command, &ctx).await,
             "mdn" => commands::mdn::run(&command.data.options).await,
@@ -41,8 +41,15 @@ pub async fn ready(ctx: Context) {
             Ok(_) => info!("部分ツイート fetched successfully."),
             Err(why) => error!("Error fetching 部分ツイート: {:?}", why),
         }
+
+        // 期限が近い todo と期限を過ぎた todo を通知する
+        let todo_processor = crate::scheduler::todo::ProcesserStruct;
+        match todo_processor.run(&ctx).await {
+            Ok(_) => info!("todo reminders sent successfully."),
+            Err(why) => error!("Error sending todo reminders: {:?}", why),
+        }
     } else {
-        info!("RSS and 部分ツイート are not performed in development mode.");
+        info!("RSS, 部分ツイート and todo reminders are not performed in development mode.");
     }
 
     info!("bot is ready!")
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::client::HttpClient;
+use super::error::HttpError;
+use super::method::Method;
+use super::response::HttpResponse;
+use crate::utils::encode::{encode, form_encode_pairs};
+
+/// 1回分のリクエストを組み立てる。
+/// ここで設定した header はこのリクエストにだけ使われ、HttpClient の header は変更しない。
+/// # Example
+/// ```
+/// let client = HttpClient::new();
+/// let response = client
+///     .request(Method::Put, "https://example.com/items")
+///     .query("id", "1")
+///     .header("Accept", "application/json")
+///     .json(&item)
+///     .send()
+///     .await;
+/// ```
+pub struct RequestBuilder<'a> {
+    client: &'a HttpClient,
+    method: Method,
+    url: String,
+    query: Vec<(String, String)>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+    error: Option<HttpError>,
+}
+
+impl<'a> RequestBuilder<'a> {
+    pub fn new(client: &'a HttpClient, method: Method, url: &str) -> Self {
+        Self {
+            client,
+            method,
+            url: url.to_string(),
+            query: Vec::new(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// query parameter を追加する。key と value は percent-encode される。
+    pub fn query(mut self, key: &str, value: &str) -> Self {
+        self.query.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn bearer_auth(self, token: &str) -> Self {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    #[allow(dead_code)]
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// body を JSON にシリアライズして Content-Type を application/json にする
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(body) => {
+                self.body = body;
+                self.header("Content-Type", "application/json")
+            }
+            Err(why) => {
+                self.error = Some(HttpError::Protocol(format!(
+                    "failed to serialize json body: {}",
+                    why
+                )));
+                self
+            }
+        }
+    }
+
+    /// key と value の組を application/x-www-form-urlencoded の body にする
+    #[allow(dead_code)]
+    pub fn form<I, K, V>(mut self, pairs: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.body = form_encode_pairs(pairs).into_bytes();
+        self.header("Content-Type", "application/x-www-form-urlencoded")
+    }
+
+    // query parameter を url に追加する。fragment はサーバーに送らないので落とす。
+    fn build_url(&self) -> String {
+        let url = match self.url.find('#') {
+            Some(pos) => &self.url[..pos],
+            None => self.url.as_str(),
+        };
+        if self.query.is_empty() {
+            return url.to_string();
+        }
+
+        let query = self
+            .query
+            .iter()
+            .map(|(k, v)| format!("{}={}", encode(k), encode(v)))
+            .collect::<Vec<String>>()
+            .join("&");
+        let separator = match url.find('?') {
+            Some(pos) if pos + 1 == url.len() || url.ends_with('&') => "",
+            Some(_) => "&",
+            None => "?",
+        };
+        format!("{}{}{}", url, separator, query)
+    }
+
+    pub async fn send(self) -> Result<HttpResponse, HttpError> {
+        if let Some(why) = self.error {
+            return Err(why);
+        }
+        let url = self.build_url();
+
+        let mut headers = self.client.headers.clone();
+        for (key, value) in self.headers {
+            // 大文字小文字だけが違う header は上書きする
+            headers.retain(|k, _| !k.eq_ignore_ascii_case(&key));
+            headers.insert(key, value);
+        }
+
+        self.client
+            .execute_with_redirects(self.method, &url, headers, self.body)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_url() {
+        let client = HttpClient::new();
+        let url = RequestBuilder::new(&client, Method::Get, "https://example.com/search#top")
+            .query("q", "rust 言語")
+            .query("lang", "ja&en")
+            .build_url();
+        assert_eq!(
+            url,
+            "https://example.com/search?q=rust%20%E8%A8%80%E8%AA%9E&lang=ja%26en"
+        );
+
+        let url = RequestBuilder::new(&client, Method::Get, "https://example.com/?a=1")
+            .query("b", "2")
+            .build_url();
+        assert_eq!(url, "https://example.com/?a=1&b=2");
+
+        let url = RequestBuilder::new(&client, Method::Get, "https://example.com/").build_url();
+        assert_eq!(url, "https://example.com/");
+    }
+}
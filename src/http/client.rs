@@ -1,46 +1,162 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
 
+use super::builder::RequestBuilder;
+use super::error::{HttpError, TimeoutKind};
+pub use super::method::Method;
+use super::pool::ConnectionPool;
 use super::request::HttpRequest;
 use super::response::HttpResponse;
+pub use super::retry::RetryPolicy;
+pub use super::status::StatusCode;
+use crate::url::url::Url;
+
+// リダイレクトを辿る回数のデフォルト値
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// 接続・読み込み・リクエスト全体のタイムアウト
+#[derive(Clone, Copy, Debug)]
+pub struct Timeouts {
+    pub connect: Duration,
+    pub read: Duration,
+    pub total: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            read: Duration::from_secs(30),
+            total: Duration::from_secs(60),
+        }
+    }
+}
 
 fn default_headers() -> HashMap<String, String> {
     let mut headers = HashMap::new();
     headers.insert("User-Agent".to_string(), "Rust".to_string());
     headers.insert("Accept".to_string(), "*/*".to_string());
+    headers.insert("Accept-Encoding".to_string(), "gzip, deflate".to_string());
     headers.insert("Connection".to_string(), "close".to_string());
     headers
 }
 
-pub enum StatusCode {
-    OK = 200,
-    Created = 201,
-    Accepted = 202,
-    NoContent = 204,
-    MovedPermanently = 301,
-    Found = 302,
-    NotModified = 304,
-    BadRequest = 400,
-    Unauthorized = 401,
-    Forbidden = 403,
-    NotFound = 404,
-    MethodNotAllowed = 405,
-    RequestTimeout = 408,
-    TooManyRequests = 429,
-    InternalServerError = 500,
-    Unsupported = 0,
-}
-
+/// clone したクライアント同士は keep-alive の接続プールを共有する
+#[derive(Clone)]
 pub struct HttpClient {
-    pub url: String,
     pub headers: HashMap<String, String>,
+    pub max_redirects: usize,
+    pub timeouts: Timeouts,
+    pub decompress: bool,
+    pub retry: Option<RetryPolicy>,
+    pool: Option<Arc<ConnectionPool>>,
 }
 
 impl HttpClient {
     pub fn new() -> Self {
         Self {
-            url: "".to_string(),
             headers: default_headers(),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            timeouts: Timeouts::default(),
+            decompress: true,
+            retry: None,
+            pool: None,
+        }
+    }
+
+    /// Retry on 429/5xx and transient network errors. Only idempotent methods are retried by default.
+    /// # Example
+    /// ```
+    /// let mut client = HttpClient::new();
+    /// let response = client.retry(RetryPolicy::new(3)).get("https://example.com").await;
+    /// ```
+    pub fn retry(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Enable or disable gzip/deflate decompression of response bodies (enabled by default)
+    /// # Example
+    /// ```
+    /// let mut client = HttpClient::new();
+    /// let response = client.decompress(false).get("https://example.com").await;
+    /// ```
+    #[allow(dead_code)]
+    pub fn decompress(&mut self, decompress: bool) -> &mut Self {
+        self.decompress = decompress;
+        if decompress {
+            self.headers
+                .insert("Accept-Encoding".to_string(), "gzip, deflate".to_string());
+        } else {
+            self.headers.remove("Accept-Encoding");
         }
+        self
+    }
+
+    /// Reuse connections with keep-alive. Idle connections are closed after `idle_timeout`.
+    /// # Example
+    /// ```
+    /// let mut client = HttpClient::new();
+    /// client.keep_alive(Duration::from_secs(30));
+    /// let feed1 = client.get("https://example.com/feed1.xml").await;
+    /// let feed2 = client.get("https://example.com/feed2.xml").await;
+    /// ```
+    pub fn keep_alive(&mut self, idle_timeout: Duration) -> &mut Self {
+        self.pool = Some(Arc::new(ConnectionPool::new(idle_timeout)));
+        self.headers
+            .insert("Connection".to_string(), "keep-alive".to_string());
+        self
+    }
+
+    /// Set timeout for DNS lookup, TCP connect and TLS handshake
+    /// # Example
+    /// ```
+    /// let mut client = HttpClient::new();
+    /// let response = client.connect_timeout(Duration::from_secs(5)).get("https://example.com").await;
+    /// ```
+    #[allow(dead_code)]
+    pub fn connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.connect = timeout;
+        self
+    }
+
+    /// Set timeout for waiting on each read from the server
+    /// # Example
+    /// ```
+    /// let mut client = HttpClient::new();
+    /// let response = client.read_timeout(Duration::from_secs(10)).get("https://example.com").await;
+    /// ```
+    #[allow(dead_code)]
+    pub fn read_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.read = timeout;
+        self
+    }
+
+    /// Set timeout for the whole request including redirects
+    /// # Example
+    /// ```
+    /// let mut client = HttpClient::new();
+    /// let response = client.timeout(Duration::from_secs(30)).get("https://example.com").await;
+    /// ```
+    #[allow(dead_code)]
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.total = timeout;
+        self
+    }
+
+    /// Set the maximum number of redirects to follow. 0 disables redirects.
+    /// # Example
+    /// ```
+    /// let mut client = HttpClient::new();
+    /// let response = client.max_redirects(0).get("https://example.com").await;
+    /// ```
+    #[allow(dead_code)]
+    pub fn max_redirects(&mut self, max_redirects: usize) -> &mut Self {
+        self.max_redirects = max_redirects;
+        self
     }
 
     /// Set header
@@ -49,6 +165,7 @@ impl HttpClient {
     /// let mut client = HttpClient::new();
     /// let response = client.set_header("Content-Type", "application/json").post("https://example.com", "{}").await;
     /// ```
+    #[allow(dead_code)]
     pub fn set_header(&mut self, key: &str, value: &str) -> &mut Self {
         self.headers.insert(key.to_string(), value.to_string());
         self
@@ -61,6 +178,7 @@ impl HttpClient {
     /// let mut client = HttpClient::new();
     /// let response = client.header_author(token).post("https://example.com", "{}").await;
     /// ```
+    #[allow(dead_code)]
     pub fn header_authorization(&mut self, token: String) -> &mut Self {
         self.headers
             .insert("Authorization".to_string(), format!("Bearer {}", token));
@@ -73,10 +191,8 @@ impl HttpClient {
     /// let mut client = HttpClient::new();
     /// let response = client.get("https://example.com").await;
     /// ```
-    pub async fn get(&self, url: &str) -> Result<HttpResponse, std::io::Error> {
-        let mut request = HttpRequest::new(url, self.headers.clone());
-        request.get().await;
-        self.send(request).await
+    pub async fn get(&self, url: &str) -> Result<HttpResponse, HttpError> {
+        self.request(Method::Get, url).send().await
     }
 
     /// Send POST request
@@ -85,13 +201,684 @@ impl HttpClient {
     /// let mut client = HttpClient::new();
     /// let response = client.post("https://example.com", "{}").await;
     /// ```
-    pub async fn post(&self, url: &str, body: String) -> Result<HttpResponse, std::io::Error> {
-        let mut request = HttpRequest::new(url, self.headers.clone());
-        request.post(body.as_str()).await;
-        self.send(request).await
+    #[allow(dead_code)]
+    pub async fn post(&self, url: &str, body: String) -> Result<HttpResponse, HttpError> {
+        self.request(Method::Post, url).body(body).send().await
+    }
+
+    /// Build a request with any method, query parameters and per-request headers
+    /// # Example
+    /// ```
+    /// let client = HttpClient::new();
+    /// let response = client
+    ///     .request(Method::Delete, "https://example.com/items")
+    ///     .query("id", "1")
+    ///     .bearer_auth(&token)
+    ///     .send()
+    ///     .await;
+    /// ```
+    pub fn request(&self, method: Method, url: &str) -> RequestBuilder<'_> {
+        RequestBuilder::new(self, method, url)
     }
 
-    pub async fn send(&self, request: HttpRequest) -> Result<HttpResponse, std::io::Error> {
-        request.send().await
+    // リダイレクトを辿りながらリクエストを送る。全体に timeouts.total のタイムアウトをかける。
+    pub(super) async fn execute_with_redirects(
+        &self,
+        method: Method,
+        url: &str,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Result<HttpResponse, HttpError> {
+        self.with_timeout(self.follow_redirects(method, url, headers, body))
+            .await
+    }
+
+    #[allow(dead_code)]
+    pub async fn send(&self, request: HttpRequest) -> Result<HttpResponse, HttpError> {
+        self.with_timeout(self.execute(&request)).await
+    }
+
+    // リクエストを送り、retry が設定されていれば一時的なエラーの間やり直す
+    async fn execute(&self, request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+        let policy = match &self.retry {
+            Some(policy) if request.is_idempotent() || policy.retry_non_idempotent => policy,
+            _ => return self.execute_once(request).await,
+        };
+
+        let mut attempt = 0;
+        loop {
+            let delay = match self.execute_once(request).await {
+                Ok(response) => match policy.retry_response(&response, attempt) {
+                    Some(delay) => {
+                        warn!(
+                            "retrying {} after {:?}: {}",
+                            request.url, delay, response.status_code
+                        );
+                        delay
+                    }
+                    None => return Ok(response),
+                },
+                Err(why) => match policy.retry_error(&why, attempt) {
+                    Some(delay) => {
+                        warn!("retrying {} after {:?}: {}", request.url, delay, why);
+                        delay
+                    }
+                    None => return Err(why),
+                },
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    // リクエストを1回送り、必要であれば body を展開する
+    async fn execute_once(&self, request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+        let mut response = request.send(&self.timeouts, self.pool.as_deref()).await?;
+        if self.decompress {
+            response.decompress()?;
+        }
+        Ok(response)
+    }
+
+    // リクエスト全体に timeouts.total のタイムアウトをかける
+    async fn with_timeout<F>(&self, future: F) -> Result<HttpResponse, HttpError>
+    where
+        F: Future<Output = Result<HttpResponse, HttpError>>,
+    {
+        match tokio::time::timeout(self.timeouts.total, future).await {
+            Ok(result) => result,
+            Err(_) => Err(HttpError::Timeout(TimeoutKind::Total)),
+        }
+    }
+
+    // リクエストを送り、3xx の Location を max_redirects 回まで辿る
+    async fn follow_redirects(
+        &self,
+        method: Method,
+        url: &str,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Result<HttpResponse, HttpError> {
+        let origin = Url::parse(url)?;
+        let mut url = url.to_string();
+        let mut headers = headers;
+        let mut method = method;
+        let mut body = body;
+        let mut visited = HashSet::new();
+
+        loop {
+            let request = HttpRequest::new(method, &url, headers.clone(), body.clone())?;
+            let response = self.execute(&request).await?;
+
+            let location = match response.status_code {
+                StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT => response.header("Location"),
+                _ => None,
+            };
+            let location = match location {
+                Some(location) if self.max_redirects > 0 => location,
+                _ => return Ok(response),
+            };
+
+            visited.insert(url.clone());
+            if visited.len() > self.max_redirects {
+                return Err(HttpError::Protocol(format!("too many redirects: {}", url)));
+            }
+
+            let next = match Url::parse(&url)?.join(location) {
+                Ok(next) => next.to_string(),
+                Err(why) => {
+                    return Err(HttpError::Protocol(format!(
+                        "invalid redirect location {}: {}",
+                        location, why
+                    )))
+                }
+            };
+            if visited.contains(&next) {
+                return Err(HttpError::Protocol(format!(
+                    "redirect loop detected: {}",
+                    next
+                )));
+            }
+
+            // 303 See Other は HEAD 以外のメソッドを GET に変更して body を捨てる
+            if response.status_code == StatusCode::SEE_OTHER && method != Method::Head {
+                method = Method::Get;
+                body = Vec::new();
+                remove_headers(&mut headers, &["Content-Type"]);
+            }
+            // 別のオリジンに認証情報を送らない。https から http への場合は平文で漏れてしまう
+            if !same_origin(&origin, &Url::parse(&next)?) {
+                remove_headers(&mut headers, &["Authorization", "Cookie"]);
+            }
+            url = next;
+        }
+    }
+}
+
+fn same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme() == b.scheme() && a.host_str() == b.host_str() && a.port() == b.port()
+}
+
+// ヘッダー名は大文字小文字を区別しない
+fn remove_headers(headers: &mut HashMap<String, String>, names: &[&str]) {
+    headers.retain(|name, _| {
+        !names
+            .iter()
+            .any(|removed| removed.eq_ignore_ascii_case(name))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::pool::PoolKey;
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // responses を順番に1接続ずつ返すサーバーを立てて、受け取ったリクエストを返す
+    async fn serve(responses: Vec<String>) -> (u16, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..n]).to_string());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (port, handle)
+    }
+
+    #[tokio::test]
+    async fn test_follow_redirects() {
+        let (port, handle) = serve(vec![
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: /feed/\r\n\r\n".to_string(),
+            "HTTP/1.1 302 Found\r\nlocation: rss.xml\r\n\r\n".to_string(),
+            "HTTP/1.1 200 OK\r\n\r\n<rss></rss>".to_string(),
+        ])
+        .await;
+
+        let client = HttpClient::new();
+        let response = client
+            .get(&format!("http://127.0.0.1:{}/old", port))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, StatusCode::OK);
+        assert_eq!(response.text(), "<rss></rss>");
+        assert_eq!(
+            response.url,
+            format!("http://127.0.0.1:{}/feed/rss.xml", port)
+        );
+
+        let requests = handle.await.unwrap();
+        assert!(requests[1].starts_with("GET /feed/"));
+        assert!(requests[2].starts_with("GET /feed/rss.xml"));
+    }
+
+    #[tokio::test]
+    async fn test_see_other_rewrites_to_get() {
+        let (port, handle) = serve(vec![
+            "HTTP/1.1 303 See Other\r\nLocation: /done\r\n\r\n".to_string(),
+            "HTTP/1.1 200 OK\r\n\r\nok".to_string(),
+        ])
+        .await;
+
+        let client = HttpClient::new();
+        let response = client
+            .post(&format!("http://127.0.0.1:{}/form", port), "{}".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.text(), "ok");
+
+        let requests = handle.await.unwrap();
+        assert!(requests[0].starts_with("POST /form"));
+        assert!(requests[1].starts_with("GET /done"));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_drops_credentials() {
+        let (other_port, other) = serve(vec!["HTTP/1.1 200 OK\r\n\r\nok".to_string()]).await;
+        let (port, handle) = serve(vec![
+            "HTTP/1.1 302 Found\r\nLocation: /same\r\n\r\n".to_string(),
+            format!(
+                "HTTP/1.1 303 See Other\r\nLocation: http://127.0.0.1:{}/other\r\n\r\n",
+                other_port
+            ),
+        ])
+        .await;
+
+        let client = HttpClient::new();
+        let response = client
+            .request(Method::Post, &format!("http://127.0.0.1:{}/form", port))
+            .body("{}")
+            .bearer_auth("secret")
+            .header("cookie", "session=secret")
+            .header("Content-Type", "application/json")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text(), "ok");
+
+        // 同じオリジンには送る
+        let requests = handle.await.unwrap();
+        assert!(requests[1].contains("Bearer secret"));
+        // 別のポートには送らない
+        let requests = other.await.unwrap();
+        assert!(requests[0].starts_with("GET /other"));
+        assert!(!requests[0].to_lowercase().contains("authorization"));
+        assert!(!requests[0].to_lowercase().contains("cookie"));
+        assert!(!requests[0].to_lowercase().contains("content-type"));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_loop_and_limit() {
+        let (port, _) = serve(vec![
+            "HTTP/1.1 302 Found\r\nLocation: /b\r\n\r\n".to_string(),
+            "HTTP/1.1 302 Found\r\nLocation: /a\r\n\r\n".to_string(),
+        ])
+        .await;
+        let client = HttpClient::new();
+        let result = client.get(&format!("http://127.0.0.1:{}/a", port)).await;
+        assert!(result.is_err());
+
+        let (port, _) = serve(vec![
+            "HTTP/1.1 302 Found\r\nLocation: /1\r\n\r\n".to_string(),
+            "HTTP/1.1 302 Found\r\nLocation: /2\r\n\r\n".to_string(),
+        ])
+        .await;
+        let mut client = HttpClient::new();
+        let result = client
+            .max_redirects(1)
+            .get(&format!("http://127.0.0.1:{}/0", port))
+            .await;
+        assert!(result.is_err());
+
+        let (port, _) = serve(vec![
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: /new\r\n\r\n".to_string(),
+        ])
+        .await;
+        let mut client = HttpClient::new();
+        let response = client
+            .max_redirects(0)
+            .get(&format!("http://127.0.0.1:{}/old", port))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, StatusCode::MOVED_PERMANENTLY);
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        // 誰も listen していないポートには接続できない
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let client = HttpClient::new();
+        let result = client.get(&format!("http://127.0.0.1:{}/", port)).await;
+        assert!(matches!(result, Err(HttpError::Connect(_))));
+
+        let result = client.get("http://nonexistent.invalid/").await;
+        assert!(matches!(result, Err(HttpError::Dns(_))));
+
+        // HTTP ではない応答
+        let (port, _) = serve(vec!["SSH-2.0-OpenSSH\r\n".to_string()]).await;
+        let result = client.get(&format!("http://127.0.0.1:{}/", port)).await;
+        assert!(matches!(result, Err(HttpError::Protocol(_))));
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        // 接続は受け付けるが何も返さないサーバー
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let mut client = HttpClient::new();
+        let result = client
+            .read_timeout(Duration::from_millis(50))
+            .get(&format!("http://127.0.0.1:{}/", port))
+            .await;
+        assert!(matches!(result, Err(HttpError::Timeout(TimeoutKind::Read))));
+
+        let mut client = HttpClient::new();
+        let result = client
+            .timeout(Duration::from_millis(50))
+            .get(&format!("http://127.0.0.1:{}/", port))
+            .await;
+        assert!(matches!(
+            result,
+            Err(HttpError::Timeout(TimeoutKind::Total))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let mut accepted = 0;
+            // 1つ目の接続で Content-Length と chunked のレスポンスを1回ずつ返す
+            let (mut socket, _) = listener.accept().await.unwrap();
+            accepted += 1;
+            let mut buf = vec![0; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst")
+                .await
+                .unwrap();
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nsecond\r\n0\r\n\r\n",
+                )
+                .await
+                .unwrap();
+            // 3つ目は Connection: close なので次は新しい接続になる
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 5\r\n\r\nthird",
+                )
+                .await
+                .unwrap();
+            let (mut socket, _) = listener.accept().await.unwrap();
+            accepted += 1;
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nfourth")
+                .await
+                .unwrap();
+            accepted
+        });
+
+        let mut client = HttpClient::new();
+        client.keep_alive(Duration::from_secs(30));
+        // clone したクライアントでもプールは共有される
+        let cloned = client.clone();
+        let url = format!("http://127.0.0.1:{}/", port);
+        assert_eq!(client.get(&url).await.unwrap().text(), "first");
+        assert_eq!(client.pool.as_ref().unwrap().idle_count(), 1);
+        assert_eq!(cloned.get(&url).await.unwrap().text(), "second");
+        assert_eq!(client.get(&url).await.unwrap().text(), "third");
+        assert_eq!(client.pool.as_ref().unwrap().idle_count(), 0);
+        assert_eq!(cloned.get(&url).await.unwrap().text(), "fourth");
+        assert_eq!(handle.await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_retry() {
+        // serve はレスポンスを返すと接続を閉じるので、プールの接続は毎回閉じられている
+        let (port, handle) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst".to_string(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond".to_string(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nthird".to_string(),
+        ])
+        .await;
+        let mut client = HttpClient::new();
+        client.keep_alive(Duration::from_secs(30));
+        let url = format!("http://127.0.0.1:{}/", port);
+        assert_eq!(client.get(&url).await.unwrap().text(), "first");
+        // 何も返さずに閉じられた接続は新しい接続でやり直す
+        assert_eq!(client.get(&url).await.unwrap().text(), "second");
+        // POST はプールの接続を使わない
+        let response = client.post(&url, "body".to_string()).await.unwrap();
+        assert_eq!(response.text(), "third");
+        assert_eq!(handle.await.unwrap().len(), 3);
+
+        // レスポンスを受け取った後のエラーはやり直さない
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst")
+                .await
+                .unwrap();
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket.write_all(b"garbage\r\n\r\n").await.unwrap();
+            tokio::time::timeout(Duration::from_millis(100), listener.accept())
+                .await
+                .is_err()
+        });
+        let url = format!("http://127.0.0.1:{}/", port);
+        assert_eq!(client.get(&url).await.unwrap().text(), "first");
+        assert!(matches!(
+            client.get(&url).await,
+            Err(HttpError::Protocol(_))
+        ));
+        assert!(handle.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_idle_eviction() {
+        let (port, _) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_string(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_string(),
+        ])
+        .await;
+
+        let mut client = HttpClient::new();
+        client.keep_alive(Duration::from_millis(10));
+        let url = format!("http://127.0.0.1:{}/", port);
+        assert_eq!(client.get(&url).await.unwrap().text(), "ok");
+        assert_eq!(client.pool.as_ref().unwrap().idle_count(), 1);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        // idle_timeout を過ぎた接続は取り出されずに捨てられる
+        let key = PoolKey {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: false,
+        };
+        assert!(client.pool.as_ref().unwrap().take(&key).is_none());
+        assert_eq!(client.pool.as_ref().unwrap().idle_count(), 0);
+        assert_eq!(client.get(&url).await.unwrap().text(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_decompress() {
+        use flate2::write::{GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(b"gzip body").unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut deflate = ZlibEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(b"deflate body").unwrap();
+        let deflate = deflate.finish().unwrap();
+
+        // gzip を chunked で2つに分けて返す
+        let (first, second) = gzip.split_at(gzip.len() / 2);
+        let mut chunked =
+            b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n"
+                .to_vec();
+        for chunk in [first, second] {
+            chunked.extend(format!("{:x}\r\n", chunk.len()).as_bytes());
+            chunked.extend(chunk);
+            chunked.extend(b"\r\n");
+        }
+        chunked.extend(b"0\r\n\r\n");
+        let mut zlib = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: deflate\r\nContent-Length: {}\r\n\r\n",
+            deflate.len()
+        )
+        .into_bytes();
+        zlib.extend(&deflate);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in [chunked.clone(), zlib, chunked] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..n]).to_string());
+                socket.write_all(&response).await.unwrap();
+            }
+            requests
+        });
+
+        let url = format!("http://127.0.0.1:{}/", port);
+        let mut client = HttpClient::new();
+        assert_eq!(client.get(&url).await.unwrap().text(), "gzip body");
+        assert_eq!(client.get(&url).await.unwrap().text(), "deflate body");
+        // 展開しない設定の場合はそのままのバイト列を返す
+        let response = client.decompress(false).get(&url).await.unwrap();
+        assert_eq!(response.bytes(), &gzip[..]);
+
+        let requests = handle.await.unwrap();
+        assert!(requests[0].contains("Accept-Encoding: gzip, deflate\r\n"));
+        assert!(!requests[2].contains("Accept-Encoding"));
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let (port, handle) = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\n\r\n".to_string(),
+            "HTTP/1.1 429 Too Many Requests\r\n\r\n".to_string(),
+            "HTTP/1.1 200 OK\r\n\r\nok".to_string(),
+        ])
+        .await;
+        let mut policy = RetryPolicy::new(3);
+        policy.base_delay = Duration::from_millis(1);
+        let mut client = HttpClient::new();
+        let response = client
+            .retry(policy.clone())
+            .get(&format!("http://127.0.0.1:{}/", port))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, StatusCode::OK);
+        assert_eq!(handle.await.unwrap().len(), 3);
+
+        // max_attempts に達したら最後のレスポンスを返す
+        let (port, _) = serve(vec![
+            "HTTP/1.1 502 Bad Gateway\r\n\r\n".to_string(),
+            "HTTP/1.1 502 Bad Gateway\r\n\r\n".to_string(),
+        ])
+        .await;
+        let mut client = HttpClient::new();
+        let mut two_attempts = policy.clone();
+        two_attempts.max_attempts = 2;
+        let response = client
+            .retry(two_attempts)
+            .get(&format!("http://127.0.0.1:{}/", port))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, StatusCode::BAD_GATEWAY);
+
+        // POST はデフォルトではやり直さない
+        let (port, _) = serve(vec!["HTTP/1.1 503 Service Unavailable\r\n\r\n".to_string()]).await;
+        let mut client = HttpClient::new();
+        let response = client
+            .retry(policy.clone())
+            .post(&format!("http://127.0.0.1:{}/", port), "{}".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, StatusCode::SERVICE_UNAVAILABLE);
+
+        // Retry-After が max_delay より長い場合は待たずに返す
+        let (port, _) = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 3600\r\n\r\n".to_string(),
+        ])
+        .await;
+        let mut client = HttpClient::new();
+        let response = client
+            .retry(policy)
+            .get(&format!("http://127.0.0.1:{}/", port))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_request_builder() {
+        let (port, handle) = serve(vec![
+            "HTTP/1.1 200 OK\r\n\r\n".to_string(),
+            "HTTP/1.1 204 No Content\r\n\r\n".to_string(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n".to_string(),
+        ])
+        .await;
+
+        #[derive(serde::Serialize)]
+        struct Item {
+            name: String,
+        }
+
+        let mut client = HttpClient::new();
+        client.set_header("Accept", "*/*");
+        let url = format!("http://127.0.0.1:{}/items", port);
+        let response = client
+            .request(Method::Put, &url)
+            .query("id", "a b")
+            .header("accept", "application/json")
+            .json(&Item {
+                name: "\"todo\"".to_string(),
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, StatusCode::OK);
+
+        let response = client
+            .request(Method::Delete, &url)
+            .query("id", "1")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, StatusCode::NO_CONTENT);
+
+        // HEAD のレスポンスは Content-Length があっても body を読まない
+        let response = client.request(Method::Head, &url).send().await.unwrap();
+        assert!(response.bytes().is_empty());
+
+        let requests = handle.await.unwrap();
+        assert!(requests[0].starts_with("PUT /items?id=a%20b HTTP/1.1\r\n"));
+        assert!(requests[0].contains("accept: application/json\r\n"));
+        // per-request の header はクライアントの header を変更しない
+        assert!(!requests[0].contains("Accept: */*"));
+        assert_eq!(client.headers.get("Accept"), Some(&"*/*".to_string()));
+        assert!(requests[0].contains("Content-Type: application/json\r\n"));
+        assert!(requests[0].contains("Content-Length: 19\r\n"));
+        assert!(requests[0].ends_with("\r\n\r\n{\"name\":\"\\\"todo\\\"\"}"));
+        assert!(requests[1].starts_with("DELETE /items?id=1 HTTP/1.1\r\n"));
+        assert!(requests[2].starts_with("HEAD /items HTTP/1.1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_get_plain_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 1024];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nhello")
+                .await
+                .unwrap();
+        });
+
+        let client = HttpClient::new();
+        let response = client
+            .get(&format!("http://127.0.0.1:{}/feed", port))
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, StatusCode::OK);
+        assert_eq!(response.text(), "hello");
     }
 }
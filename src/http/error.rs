@@ -0,0 +1,66 @@
+use std::fmt;
+use std::io;
+
+use crate::url::error::ParseError;
+
+/// どの段階でタイムアウトしたか
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeoutKind {
+    Connect,
+    Read,
+    Total,
+}
+
+/// HTTP クライアントで発生するエラー
+#[derive(Debug)]
+pub enum HttpError {
+    /// リクエストする URL が不正
+    InvalidUrl(ParseError),
+    /// 名前解決に失敗した
+    Dns(String),
+    /// TCP の接続に失敗した
+    Connect(io::Error),
+    /// TLS のハンドシェイクに失敗した
+    Tls(String),
+    /// 接続・読み込み・リクエスト全体のいずれかがタイムアウトした
+    Timeout(TimeoutKind),
+    /// レスポンスが HTTP として不正、もしくはリダイレクトが辿れない
+    Protocol(String),
+    /// 上記以外の I/O エラー
+    Io(io::Error),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::InvalidUrl(why) => write!(f, "invalid url: {}", why),
+            HttpError::Dns(host) => write!(f, "failed to resolve host: {}", host),
+            HttpError::Connect(why) => write!(f, "failed to connect: {}", why),
+            HttpError::Tls(why) => write!(f, "tls error: {}", why),
+            HttpError::Timeout(kind) => write!(f, "{:?} timeout", kind),
+            HttpError::Protocol(why) => write!(f, "protocol error: {}", why),
+            HttpError::Io(why) => write!(f, "io error: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<ParseError> for HttpError {
+    fn from(why: ParseError) -> Self {
+        HttpError::InvalidUrl(why)
+    }
+}
+
+// レスポンスの読み込み中に発生した io::Error を種類ごとに振り分ける
+impl From<io::Error> for HttpError {
+    fn from(why: io::Error) -> Self {
+        match why.kind() {
+            io::ErrorKind::TimedOut => HttpError::Timeout(TimeoutKind::Read),
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+                HttpError::Protocol(why.to_string())
+            }
+            _ => HttpError::Io(why),
+        }
+    }
+}
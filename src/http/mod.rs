@@ -1,4 +1,11 @@
+mod builder;
 pub mod client;
+pub mod error;
+mod method;
+mod pool;
 mod request;
 mod response;
+mod retry;
+mod status;
+mod stream;
 mod tls;
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::stream::HttpStream;
+
+// 1ホストあたりに保持しておく待機中の接続の上限
+const MAX_IDLE_PER_HOST: usize = 4;
+
+/// 接続先ごとのキー。同じ host:port でも TLS の有無が違えば別の接続として扱う。
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+}
+
+struct IdleConnection {
+    stream: HttpStream,
+    idle_since: Instant,
+}
+
+/// keep-alive の接続をホストごとに保持しておくプール
+pub struct ConnectionPool {
+    idle: Mutex<HashMap<PoolKey, Vec<IdleConnection>>>,
+    idle_timeout: Duration,
+}
+
+impl ConnectionPool {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            idle: Mutex::new(HashMap::new()),
+            idle_timeout,
+        }
+    }
+
+    /// 待機中の接続があれば取り出す。idle_timeout を過ぎた接続は捨てる。
+    pub fn take(&self, key: &PoolKey) -> Option<HttpStream> {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        self.evict_expired(&mut idle);
+
+        let connections = idle.get_mut(key)?;
+        // 最後に返された接続ほど生きている可能性が高いので後ろから使う
+        let connection = connections.pop();
+        if connections.is_empty() {
+            idle.remove(key);
+        }
+        connection.map(|connection| connection.stream)
+    }
+
+    /// レスポンスを読み切った接続をプールに戻す
+    pub fn put(&self, key: PoolKey, stream: HttpStream) {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        self.evict_expired(&mut idle);
+
+        let connections = idle.entry(key).or_default();
+        if connections.len() >= MAX_IDLE_PER_HOST {
+            connections.remove(0);
+        }
+        connections.push(IdleConnection {
+            stream,
+            idle_since: Instant::now(),
+        });
+    }
+
+    fn evict_expired(&self, idle: &mut HashMap<PoolKey, Vec<IdleConnection>>) {
+        let idle_timeout = self.idle_timeout;
+        idle.retain(|_, connections| {
+            connections.retain(|connection| connection.idle_since.elapsed() < idle_timeout);
+            !connections.is_empty()
+        });
+    }
+
+    #[cfg(test)]
+    pub fn idle_count(&self) -> usize {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        idle.values().map(|connections| connections.len()).sum()
+    }
+}
@@ -1,108 +1,271 @@
 use std::collections::HashMap;
-use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 
+use super::client::Timeouts;
+use super::error::HttpError;
+use super::method::Method;
+use super::pool::{ConnectionPool, PoolKey};
 use super::response::HttpResponse;
-use super::tls::TlsConnectorBuilder;
+use super::stream::HttpStream;
+use crate::url::error::ParseError;
+use crate::url::host::Host;
 use crate::url::url::Url;
 
 pub struct HttpRequest {
     pub url: Url,
+    /// 接続先の host。IPv6 は [] をつけない。
     pub host: String,
     pub port: u16,
     pub path: String,
-    pub query: HashMap<String, String>,
+    /// url に書かれたままの query。順番や重複、エンコードを変えずにそのまま送る。
+    pub query: Option<String>,
     pub headers: HashMap<String, String>,
-    request: String,
+    pub method: Method,
+    pub body: Vec<u8>,
 }
 
 impl HttpRequest {
-    pub fn new(url: &str, headers: HashMap<String, String>) -> Self {
-        let url = Url::parse(url);
-        let host = url.host();
+    pub fn new(
+        method: Method,
+        url: &str,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Result<Self, HttpError> {
+        let url = Url::parse(url)?;
+        let host = match url.host() {
+            Some(Host::Ipv6(addr)) => addr.to_string(),
+            Some(host) if !host.to_string().is_empty() => host.to_string(),
+            _ => return Err(HttpError::InvalidUrl(ParseError::EmptyHost)),
+        };
         let port = url.port();
         let path = url.path();
         let headers = headers.into_iter().collect();
-        let query = url.query_pairs();
+        let query = url.query();
 
-        Self {
+        Ok(Self {
             url,
             host,
             port,
             path,
             query,
             headers,
-            request: String::new(),
+            method,
+            body,
+        })
+    }
+
+    pub fn is_idempotent(&self) -> bool {
+        self.method.is_idempotent()
+    }
+
+    // リクエストラインの path と query の部分
+    fn target(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.clone(),
         }
     }
 
-    async fn init_stream(&self) -> tokio_native_tls::TlsStream<TcpStream> {
-        let tcp_stream = match TcpStream::connect((self.host.as_str(), self.port)).await {
-            Ok(tcp_stream) => tcp_stream,
-            Err(why) => panic!("tcp stream error: {:?}", why),
-        };
-        let tls_stream = match TlsConnectorBuilder::new()
-            .connector
-            .connect(self.host.as_str(), tcp_stream)
-            .await
-        {
-            Ok(tls_stream) => tls_stream,
-            Err(why) => panic!("tls stream error: {:?}", why),
-        };
+    // Host header にはデフォルト以外の port もつける
+    fn host_header(&self) -> String {
+        match self.url.explicit_port() {
+            Some(port) if port != self.default_port() => {
+                format!("{}:{}", self.url.host_str(), port)
+            }
+            _ => self.url.host_str(),
+        }
+    }
 
-        tls_stream
+    fn default_port(&self) -> u16 {
+        if self.url.is_https() {
+            443
+        } else {
+            80
+        }
     }
 
-    pub async fn get(&mut self) -> &mut HttpRequest {
-        let request = format!(
-            "GET {}?{} HTTP/1.1\r\nHost: {}\r\n{}\r\n\r\n",
-            self.path,
-            self.query
-                .iter()
-                .map(|(k, v)| format!("{}={}", k, v))
-                .collect::<Vec<String>>()
-                .join("&"),
-            self.host,
-            self.headers
-                .iter()
-                .map(|(k, v)| format!("{}: {}", k, v))
-                .collect::<Vec<String>>()
-                .join("\r\n")
+    /// 送信するリクエストをバイト列にする
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\n",
+            self.method,
+            self.target(),
+            self.host_header()
         );
+        for (key, value) in &self.headers {
+            request.push_str(&format!("{}: {}\r\n", key, value));
+        }
+        // body を送るメソッドは空でも Content-Length をつける
+        if !self.body.is_empty()
+            || matches!(self.method, Method::Post | Method::Put | Method::Patch)
+        {
+            request.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        request.push_str("\r\n");
 
-        self.request = request.clone();
+        let mut request = request.into_bytes();
+        request.extend_from_slice(&self.body);
+        request
+    }
 
-        self
+    // scheme が https の場合は TLS、http の場合は平文の TCP で接続する
+    async fn init_stream(&self, timeouts: &Timeouts) -> Result<HttpStream, HttpError> {
+        let mut stream = HttpStream::connect(
+            self.host.as_str(),
+            self.port,
+            self.url.is_https(),
+            timeouts.connect,
+        )
+        .await?;
+        stream.set_read_timeout(timeouts.read);
+        Ok(stream)
     }
 
-    pub async fn post(&mut self, body: &str) -> &mut HttpRequest {
-        let content_length = body.len();
-        let request = format!(
-            "POST {} HTTP/1.1\r\nHost: {}\r\n{}\r\nContent-Length: {}\r\n\r\n{}",
-            self.path,
-            self.host,
-            self.headers
-                .iter()
-                .map(|(k, v)| format!("{}: {}", k, v))
-                .collect::<Vec<String>>()
-                .join("\r\n"),
-            content_length,
-            body
-        );
+    pub async fn send(
+        &self,
+        timeouts: &Timeouts,
+        pool: Option<&ConnectionPool>,
+    ) -> Result<HttpResponse, HttpError> {
+        let pool = match pool {
+            Some(pool) => pool,
+            None => {
+                let stream = self.init_stream(timeouts).await?;
+                let (response, _) = self.send_on(stream).await.map_err(|(why, _)| why)?;
+                return Ok(response);
+            }
+        };
 
-        self.request = request.clone();
+        let key = PoolKey {
+            host: self.host.clone(),
+            port: self.port,
+            tls: self.url.is_https(),
+        };
 
-        self
-    }
+        // プールの接続はサーバー側で既に閉じられていることがあるので、
+        // 何も受け取らずに閉じられた場合は新しい接続でやり直す。
+        // やり直すと二重に処理されるかもしれない POST, PATCH は最初から新しい接続で送る
+        if let Some(stream) = self.is_idempotent().then(|| pool.take(&key)).flatten() {
+            match self.send_on(stream).await {
+                Ok((response, stream)) => {
+                    if let Some(stream) = stream {
+                        pool.put(key, stream);
+                    }
+                    return Ok(response);
+                }
+                Err((_, true)) => (),
+                Err((why, false)) => return Err(why),
+            }
+        }
 
-    pub async fn send(&self) -> Result<HttpResponse, std::io::Error> {
-        let mut stream = self.init_stream().await;
+        let stream = self.init_stream(timeouts).await?;
+        let (response, stream) = self.send_on(stream).await.map_err(|(why, _)| why)?;
+        if let Some(stream) = stream {
+            pool.put(key, stream);
+        }
+        Ok(response)
+    }
 
-        let _ = match stream.write_all(self.request.as_bytes()).await {
+    // stream にリクエストを書き込んでレスポンスを読む。
+    // 接続を再利用できる場合は stream を返す。
+    // エラーの 2 番目の値は、レスポンスを何も受け取らずに接続が閉じられたかどうか
+    async fn send_on(
+        &self,
+        stream: HttpStream,
+    ) -> Result<(HttpResponse, Option<HttpStream>), (HttpError, bool)> {
+        let mut reader = BufReader::new(stream);
+        if let Err(why) = reader.get_mut().write_all(&self.to_bytes()).await {
+            let closed = is_closed(&why);
+            return Err((why.into(), closed));
+        }
+        // レスポンスの最初のバイトが届くまで待つ
+        match reader.fill_buf().await {
+            Ok([]) => {
+                return Err((
+                    HttpError::Protocol("connection closed before response".to_string()),
+                    true,
+                ))
+            }
             Ok(_) => (),
-            Err(why) => panic!("write error: {:?}", why),
-        };
+            Err(why) => {
+                let closed = is_closed(&why);
+                return Err((why.into(), closed));
+            }
+        }
+
+        let (mut response, reusable) = HttpResponse::from_stream(&mut reader, self.method)
+            .await
+            .map_err(|why| (why, false))?;
+        response.url = self.url.to_string();
+        let reusable = reusable && reader.buffer().is_empty();
+        let stream = reader.into_inner();
+        Ok((response, if reusable { Some(stream) } else { None }))
+    }
+}
+
+// サーバーが接続を閉じたときのエラーかどうか
+fn is_closed(why: &io::Error) -> bool {
+    matches!(
+        why.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        HttpResponse::from_stream(&mut stream).await
+    fn target(url: &str) -> String {
+        HttpRequest::new(Method::Get, url, HashMap::new(), Vec::new())
+            .unwrap()
+            .target()
+    }
+
+    #[test]
+    fn test_target() {
+        assert_eq!(target("https://example.com/feed"), "/feed");
+        assert_eq!(target("https://example.com"), "/");
+        // 順番と重複した key とエンコードをそのまま残す
+        assert_eq!(
+            target("https://example.com/xrpc?feed=at%3A%2F%2Fa&limit=10&feed=at%3A%2F%2Fb"),
+            "/xrpc?feed=at%3A%2F%2Fa&limit=10&feed=at%3A%2F%2Fb"
+        );
+        assert_eq!(
+            target("https://example.com/s?sig=a+b%2Fc==&q=%E3%81%82#top"),
+            "/s?sig=a+b%2Fc==&q=%E3%81%82"
+        );
+        assert_eq!(target("https://example.com/?a?b=c"), "/?a?b=c");
+    }
+
+    #[test]
+    fn test_host_header() {
+        let request = |url: &str| {
+            let request = HttpRequest::new(Method::Get, url, HashMap::new(), Vec::new()).unwrap();
+            (request.host.clone(), request.host_header())
+        };
+        assert_eq!(
+            request("https://Example.com:443/"),
+            ("example.com".to_string(), "example.com".to_string())
+        );
+        assert_eq!(
+            request("http://127.0.0.1:8080/"),
+            ("127.0.0.1".to_string(), "127.0.0.1:8080".to_string())
+        );
+        assert_eq!(
+            request("http://[::1]:8080/"),
+            ("::1".to_string(), "[::1]:8080".to_string())
+        );
+        assert!(matches!(
+            HttpRequest::new(
+                Method::Get,
+                "mailto:bot@example.com",
+                HashMap::new(),
+                Vec::new()
+            ),
+            Err(HttpError::InvalidUrl(ParseError::EmptyHost))
+        ));
     }
 }
@@ -1,17 +1,58 @@
+use encoding_rs::{Encoding, UTF_8};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
 use std::collections::HashMap;
+use std::io::Read;
 use tokio::io::AsyncBufReadExt;
-use tokio::io::{self, AsyncReadExt};
-use tokio::net::TcpStream;
+use tokio::io::{self, AsyncRead, AsyncReadExt};
 
 use super::client::StatusCode;
+use super::error::HttpError;
+use super::method::Method;
+
+// 受け取る body の大きさの上限。サーバーが送ってくる Content-Length をそのまま信用して確保しない
+const MAX_BODY_SIZE: usize = 32 * 1024 * 1024;
+
+fn too_large(size: usize) -> HttpError {
+    HttpError::Protocol(format!("response body is too large: {} bytes", size))
+}
+
+/// reader から length バイトだけ body に追加する。途中で接続が閉じられた場合はエラー
+async fn read_body<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    body: &mut Vec<u8>,
+    length: usize,
+) -> Result<(), HttpError> {
+    // length はサーバーが送ってきた値なので、足し算があふれないように比べる
+    if length > MAX_BODY_SIZE.saturating_sub(body.len()) {
+        return Err(too_large(body.len().saturating_add(length)));
+    }
+    let expected = body.len() + length;
+    reader.take(length as u64).read_to_end(body).await?;
+    if body.len() != expected {
+        return Err(HttpError::Protocol(
+            "connection closed while reading body".to_string(),
+        ));
+    }
+    Ok(())
+}
 
 pub struct HttpResponse {
     pub status_code: StatusCode,
     pub headers: HashMap<String, String>,
-    pub body: String,
+    body: Vec<u8>,
+    /// リダイレクトを辿った後の最終的な URL
+    pub url: String,
 }
 
 impl HttpResponse {
+    /// header の名前は大文字小文字を区別せずに検索する
+    pub fn header(&self, name: &str) -> Option<&String> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
     ///
     /// # Example
     /// ```
@@ -23,52 +64,130 @@ impl HttpResponse {
     /// };
     /// ```
     pub async fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
-        let full = &self.body.as_bytes();
-        match serde_json::from_slice(full) {
+        match serde_json::from_slice(&self.body) {
             Ok(body) => Ok(body),
             Err(why) => Err(why),
         }
     }
 
-    async fn parse(
-        stream: &mut tokio_native_tls::TlsStream<TcpStream>,
-    ) -> Result<HttpResponse, std::io::Error> {
+    /// body をバイト列のまま返す
+    pub fn bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// body を文字列にデコードして返す。
+    /// Content-Type の charset、XML の prolog の encoding の順に文字コードを探し、
+    /// どちらもなければ UTF-8 として扱う。デコードできないバイトは置換文字になる。
+    /// # Example
+    /// ```
+    /// let response = client.get("https://example.com/feed.xml").await?;
+    /// let text = response.text();
+    /// ```
+    pub fn text(&self) -> String {
+        let encoding = self
+            .header("Content-Type")
+            .and_then(|content_type| charset_from_content_type(content_type))
+            .or_else(|| encoding_from_xml_prolog(&self.body))
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+            .unwrap_or(UTF_8);
+
+        let (text, _, _) = encoding.decode(&self.body);
+        text.into_owned()
+    }
+
+    /// Content-Encoding が gzip / deflate の場合に body を展開する
+    pub fn decompress(&mut self) -> Result<(), HttpError> {
+        let content_encoding = match self.header("Content-Encoding") {
+            Some(content_encoding) => content_encoding.clone(),
+            None => return Ok(()),
+        };
+
+        // 複数指定されている場合は適用された順の逆に展開する
+        for encoding in content_encoding.split(',').rev() {
+            let encoding = encoding.trim().to_ascii_lowercase();
+            let mut body = Vec::new();
+            // 展開後の大きさも MAX_BODY_SIZE までにする。小さな圧縮データが巨大に展開されることがある
+            let limit = MAX_BODY_SIZE as u64 + 1;
+            let result = match encoding.as_str() {
+                "gzip" | "x-gzip" => GzDecoder::new(&self.body[..])
+                    .take(limit)
+                    .read_to_end(&mut body),
+                // deflate は zlib 形式が正しいが、生の deflate を返すサーバーもある
+                "deflate" => ZlibDecoder::new(&self.body[..])
+                    .take(limit)
+                    .read_to_end(&mut body)
+                    .or_else(|_| {
+                        body.clear();
+                        DeflateDecoder::new(&self.body[..])
+                            .take(limit)
+                            .read_to_end(&mut body)
+                    }),
+                "identity" | "" => continue,
+                _ => {
+                    return Err(HttpError::Protocol(format!(
+                        "unsupported content-encoding: {}",
+                        encoding
+                    )))
+                }
+            };
+            if let Err(why) = result {
+                return Err(HttpError::Protocol(format!(
+                    "failed to decode {} body: {}",
+                    encoding, why
+                )));
+            }
+            if body.len() > MAX_BODY_SIZE {
+                return Err(too_large(body.len()));
+            }
+            self.body = body;
+        }
+
+        self.headers
+            .retain(|key, _| !key.eq_ignore_ascii_case("Content-Encoding"));
+        Ok(())
+    }
+
+    async fn parse<S: AsyncRead + Unpin>(
+        stream: &mut S,
+        method: Method,
+    ) -> Result<(HttpResponse, bool), HttpError> {
         let mut stream_reader = io::BufReader::new(stream);
         let mut headers = HashMap::new();
         let mut body = Vec::new();
-        let mut status_code = StatusCode::Unsupported;
 
         let mut status_line = String::new();
         stream_reader.read_line(&mut status_line).await?;
-        if let Some(code) = status_line.split_whitespace().nth(1) {
-            status_code = match code.parse::<u16>() {
-                Ok(code) => match code {
-                    200 => StatusCode::OK,
-                    201 => StatusCode::Created,
-                    202 => StatusCode::Accepted,
-                    204 => StatusCode::NoContent,
-                    301 => StatusCode::MovedPermanently,
-                    302 => StatusCode::Found,
-                    304 => StatusCode::NotModified,
-                    400 => StatusCode::BadRequest,
-                    401 => StatusCode::Unauthorized,
-                    403 => StatusCode::Forbidden,
-                    404 => StatusCode::NotFound,
-                    405 => StatusCode::MethodNotAllowed,
-                    408 => StatusCode::RequestTimeout,
-                    429 => StatusCode::TooManyRequests,
-                    500 => StatusCode::InternalServerError,
-                    _ => StatusCode::Unsupported,
-                },
-                Err(_) => StatusCode::Unsupported,
-            };
+        if !status_line.starts_with("HTTP/") {
+            return Err(HttpError::Protocol(format!(
+                "invalid status line: {:?}",
+                status_line.trim_end()
+            )));
         }
+        let status_code = match status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+        {
+            Some(code) => StatusCode::from_u16(code),
+            None => {
+                return Err(HttpError::Protocol(format!(
+                    "invalid status code: {:?}",
+                    status_line.trim_end()
+                )))
+            }
+        };
 
         // header を読み込む
         let mut chunked = false;
+        let mut content_length = None;
+        let mut connection_close = !status_line.starts_with("HTTP/1.1");
         loop {
             let mut line = String::new();
-            stream_reader.read_line(&mut line).await?;
+            if stream_reader.read_line(&mut line).await? == 0 {
+                return Err(HttpError::Protocol(
+                    "connection closed while reading headers".to_string(),
+                ));
+            }
             if line == "\r\n" {
                 break;
             }
@@ -77,62 +196,248 @@ impl HttpResponse {
             if parts.len() == 2 {
                 headers.insert(parts[0].to_string(), parts[1].to_string());
                 // Transfer-Encoding: chunked の場合は chunked で処理するため、フラグを立てる
-                if parts[0] == "Transfer-Encoding" && parts[1] == "chunked" {
+                if parts[0].eq_ignore_ascii_case("Transfer-Encoding")
+                    && parts[1].eq_ignore_ascii_case("chunked")
+                {
                     chunked = true;
                 }
+                if parts[0].eq_ignore_ascii_case("Content-Length") {
+                    content_length = parts[1].trim().parse::<usize>().ok();
+                }
+                if parts[0].eq_ignore_ascii_case("Connection") {
+                    connection_close = parts[1].eq_ignore_ascii_case("close");
+                }
             }
         }
 
-        if chunked {
+        // body の終わりが分かる場合だけ接続を再利用できる
+        let mut framed = true;
+        if method == Method::Head
+            || status_code.is_informational()
+            || status_code == StatusCode::NO_CONTENT
+            || status_code == StatusCode::NOT_MODIFIED
+        {
+            // HEAD や 204, 304 のレスポンスには body がない
+        } else if chunked {
             // Transfer-Encoding: chunked の場合は chunked で処理する
             loop {
                 let mut size_str = String::new();
                 stream_reader.read_line(&mut size_str).await?;
-                let size = match usize::from_str_radix(size_str.trim(), 16) {
+                // 5;name=value のような chunk extension は読み飛ばす
+                let size = size_str.split(';').next().unwrap_or_default().trim();
+                let size = match usize::from_str_radix(size, 16) {
                     Ok(size) => size,
                     Err(_) => {
-                        break;
+                        return Err(HttpError::Protocol(format!(
+                            "invalid chunk size: {:?}",
+                            size_str.trim_end()
+                        )))
                     }
                 };
 
                 if size == 0 {
-                    // chunk のサイズが0の場合は終了
+                    // chunk のサイズが0の場合は trailer を読み飛ばして終了
+                    loop {
+                        let mut trailer = String::new();
+                        if stream_reader.read_line(&mut trailer).await? == 0 {
+                            framed = false;
+                            break;
+                        }
+                        if trailer == "\r\n" {
+                            break;
+                        }
+                    }
                     break;
                 }
 
-                let mut buffer = vec![0; size];
-                stream_reader.read_exact(&mut buffer).await?;
-                body.extend(buffer);
+                read_body(&mut stream_reader, &mut body, size).await?;
 
                 // chunk の終わりの CRLF を読み飛ばす
                 let mut end_of_chunk = vec![0; 2];
                 stream_reader.read_exact(&mut end_of_chunk).await?;
             }
+        } else if let Some(content_length) = content_length {
+            // Content-Length がある場合はその長さだけ読む
+            read_body(&mut stream_reader, &mut body, content_length).await?;
         } else {
-            // chunked ではない場合はそのまま処理する
-            stream_reader.read_to_end(&mut body).await?;
-        }
-
-        let body_string = match String::from_utf8(body) {
-            Ok(body) => body,
-            Err(_) => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "response body is not utf-8",
-                ))
+            // どちらもない場合は接続が閉じられるまで読む
+            framed = false;
+            (&mut stream_reader)
+                .take(MAX_BODY_SIZE as u64 + 1)
+                .read_to_end(&mut body)
+                .await?;
+            if body.len() > MAX_BODY_SIZE {
+                return Err(too_large(body.len()));
             }
-        };
+        }
+        // 読みすぎたデータが残っている接続は再利用しない
+        let reusable = framed && !connection_close && stream_reader.buffer().is_empty();
 
-        Ok(HttpResponse {
+        let response = HttpResponse {
             status_code,
             headers,
-            body: body_string,
-        })
+            body,
+            url: String::new(),
+        };
+        Ok((response, reusable))
+    }
+
+    /// stream からレスポンスを読み込む。2番目の値は接続を再利用できるかどうか。
+    pub async fn from_stream<S: AsyncRead + Unpin>(
+        stream: &mut S,
+        method: Method,
+    ) -> Result<(HttpResponse, bool), HttpError> {
+        HttpResponse::parse(stream, method).await
+    }
+}
+
+// Content-Type: text/xml; charset="Shift_JIS" のような header から charset を取り出す
+fn charset_from_content_type(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let mut parts = param.splitn(2, '=');
+        let key = parts.next()?.trim();
+        let value = parts.next()?.trim().trim_matches('"');
+        if key.eq_ignore_ascii_case("charset") && !value.is_empty() {
+            Some(value.to_string())
+        } else {
+            None
+        }
+    })
+}
+
+// <?xml version="1.0" encoding="EUC-JP"?> のような prolog から encoding を取り出す
+fn encoding_from_xml_prolog(body: &[u8]) -> Option<String> {
+    if !body.starts_with(b"<?xml") {
+        return None;
+    }
+    let end = body.windows(2).position(|w| w == b"?>")?;
+    let prolog = String::from_utf8_lossy(&body[..end]);
+    let start = prolog.find("encoding")? + "encoding".len();
+    let rest = prolog[start..].trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &rest[1..];
+    let value = &value[..value.find(quote)?];
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(raw: &[u8]) -> HttpResponse {
+        let mut raw = raw;
+        let (response, _) = HttpResponse::from_stream(&mut raw, Method::Get)
+            .await
+            .unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_content_length_and_bytes() {
+        // Content-Length より後ろのデータは body に含めない
+        let response =
+            parse(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n\x00\xff\x10\x80extra").await;
+        assert_eq!(response.bytes(), &[0x00, 0xff, 0x10, 0x80]);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_size() {
+        let response = parse(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3;name=value\r\nabc\r\n2\r\nde\r\n0\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.bytes(), b"abcde");
+
+        // 不正な chunk のサイズや途中で閉じられた接続は、途中までの body を返さずにエラーにする
+        for raw in [
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\nzz\r\nde\r\n"[..],
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n"[..],
+        ] {
+            let mut raw = raw;
+            let result = HttpResponse::from_stream(&mut raw, Method::Get).await;
+            assert!(matches!(result, Err(HttpError::Protocol(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_body_size() {
+        // 巨大な Content-Length を受け取っても確保せずにエラーにする
+        let mut raw: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 99999999999\r\n\r\nabc";
+        let result = HttpResponse::from_stream(&mut raw, Method::Get).await;
+        assert!(matches!(result, Err(HttpError::Protocol(_))));
+
+        let mut raw: &[u8] =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffff\r\nabc";
+        let result = HttpResponse::from_stream(&mut raw, Method::Get).await;
+        assert!(matches!(result, Err(HttpError::Protocol(_))));
+
+        // 合計があふれる chunk のサイズ
+        let mut raw: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\nffffffffffffffff\r\nabc";
+        let result = HttpResponse::from_stream(&mut raw, Method::Get).await;
+        assert!(matches!(result, Err(HttpError::Protocol(_))));
+
+        // Content-Length より短いまま接続が閉じられた
+        let mut raw: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nabc";
+        let result = HttpResponse::from_stream(&mut raw, Method::Get).await;
+        assert!(matches!(result, Err(HttpError::Protocol(_))));
+    }
+
+    #[tokio::test]
+    async fn test_decompress_size() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        // 展開すると MAX_BODY_SIZE を超える gzip
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::best());
+        gzip.write_all(&vec![0; MAX_BODY_SIZE + 1]).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut raw = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gzip.len()
+        )
+        .into_bytes();
+        raw.extend(&gzip);
+        let mut response = parse(&raw).await;
+        assert!(matches!(response.decompress(), Err(HttpError::Protocol(_))));
+    }
+
+    #[tokio::test]
+    async fn test_text_with_charset() {
+        let (body, _, _) = encoding_rs::SHIFT_JIS.encode("こんにちは");
+        let mut raw =
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=\"Shift_JIS\"\r\n\r\n".to_vec();
+        raw.extend_from_slice(&body);
+        let response = parse(&raw).await;
+        assert_eq!(response.text(), "こんにちは");
+    }
+
+    #[tokio::test]
+    async fn test_text_with_xml_prolog() {
+        let (body, _, _) = encoding_rs::EUC_JP
+            .encode("<?xml version=\"1.0\" encoding='EUC-JP'?><rss>日本語</rss>");
+        let mut raw = b"HTTP/1.1 200 OK\r\nContent-Type: application/rss+xml\r\n\r\n".to_vec();
+        raw.extend_from_slice(&body);
+        let response = parse(&raw).await;
+        assert_eq!(
+            response.text(),
+            "<?xml version=\"1.0\" encoding='EUC-JP'?><rss>日本語</rss>"
+        );
+
+        let response = parse("HTTP/1.1 200 OK\r\n\r\nあいう".as_bytes()).await;
+        assert_eq!(response.text(), "あいう");
     }
 
-    pub async fn from_stream(
-        stream: &mut tokio_native_tls::TlsStream<TcpStream>,
-    ) -> Result<HttpResponse, std::io::Error> {
-        HttpResponse::parse(stream).await
+    #[test]
+    fn test_charset_from_content_type() {
+        assert_eq!(
+            charset_from_content_type("text/html; charset=UTF-8"),
+            Some("UTF-8".to_string())
+        );
+        assert_eq!(
+            charset_from_content_type("text/xml;Charset=\"euc-jp\""),
+            Some("euc-jp".to_string())
+        );
+        assert_eq!(charset_from_content_type("application/json"), None);
     }
 }
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+
+use super::error::{HttpError, TimeoutKind};
+use super::response::HttpResponse;
+use super::status::StatusCode;
+
+/// 一時的なエラーのときにリクエストをやり直す設定
+/// # Example
+/// ```
+/// let mut client = HttpClient::new();
+/// let response = client.retry(RetryPolicy::new(3)).get("https://example.com").await;
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// 最初のリクエストを含めた最大試行回数
+    pub max_attempts: u32,
+    /// 1回目のやり直しまでの待ち時間。以降は2倍ずつ増える
+    pub base_delay: Duration,
+    /// 待ち時間の上限。Retry-After がこれを超える場合はやり直さない
+    pub max_delay: Duration,
+    /// POST のような冪等でないリクエストもやり直すかどうか
+    pub retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            retry_non_idempotent: false,
+        }
+    }
+
+    /// attempt 回目 (0 始まり) の失敗の後に待つ時間。
+    /// base_delay * 2^attempt を上限に、その半分から全体の間でランダムに揺らす。
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(attempt))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        let half = delay / 2;
+        half + delay.mul_f64(rand::thread_rng().gen_range(0.0..=0.5))
+    }
+
+    /// レスポンスを見て、やり直すなら待ち時間を返す
+    pub fn retry_response(&self, response: &HttpResponse, attempt: u32) -> Option<Duration> {
+        if attempt + 1 >= self.max_attempts {
+            return None;
+        }
+        match response.status_code {
+            StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => (),
+            _ => return None,
+        }
+
+        match response
+            .header("Retry-After")
+            .and_then(|v| parse_retry_after(v))
+        {
+            Some(delay) if delay > self.max_delay => None,
+            Some(delay) => Some(delay),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+
+    /// エラーを見て、やり直すなら待ち時間を返す。
+    /// 名前解決や TLS、レスポンスの形式のエラーはやり直しても変わらないので対象外。
+    pub fn retry_error(&self, error: &HttpError, attempt: u32) -> Option<Duration> {
+        if attempt + 1 >= self.max_attempts {
+            return None;
+        }
+        match error {
+            HttpError::Connect(_)
+            | HttpError::Io(_)
+            | HttpError::Timeout(TimeoutKind::Connect)
+            | HttpError::Timeout(TimeoutKind::Read) => Some(self.backoff(attempt)),
+            _ => None,
+        }
+    }
+}
+
+// Retry-After は秒数か HTTP-date のどちらかで指定される
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&Utc) - Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new(5);
+        for attempt in 0..4 {
+            let expected = Duration::from_millis(500) * 2u32.pow(attempt);
+            let delay = policy.backoff(attempt);
+            assert!(delay >= expected / 2 && delay <= expected);
+        }
+        // 上限を超えない
+        assert!(policy.backoff(20) <= Duration::from_secs(30));
+        assert!(policy.backoff(u32::MAX) <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let future = (Utc::now() + chrono::Duration::seconds(100)).to_rfc2822();
+        let delay = parse_retry_after(&future).unwrap();
+        assert!(delay > Duration::from_secs(90) && delay <= Duration::from_secs(100));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
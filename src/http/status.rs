@@ -0,0 +1,180 @@
+use std::fmt;
+
+/// HTTP のステータスコード。定義されていないコードも数値のまま保持する。
+/// # Example
+/// ```
+/// match response.status_code {
+///     StatusCode::OK => (),
+///     status if status.is_server_error() => println!("server error: {}", status),
+///     status => println!("unexpected status: {}", status.as_u16()),
+/// }
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StatusCode(u16);
+
+// ステータスコードの定数と reason phrase をまとめて定義する
+macro_rules! status_codes {
+    ($(($code:expr, $name:ident, $reason:expr);)+) => {
+        impl StatusCode {
+            $(
+                #[allow(dead_code)]
+                pub const $name: StatusCode = StatusCode($code);
+            )+
+
+            /// 定義済みのステータスコードであれば reason phrase を返す
+            pub fn canonical_reason(&self) -> Option<&'static str> {
+                match self.0 {
+                    $($code => Some($reason),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+status_codes! {
+    (100, CONTINUE, "Continue");
+    (101, SWITCHING_PROTOCOLS, "Switching Protocols");
+    (102, PROCESSING, "Processing");
+    (103, EARLY_HINTS, "Early Hints");
+
+    (200, OK, "OK");
+    (201, CREATED, "Created");
+    (202, ACCEPTED, "Accepted");
+    (203, NON_AUTHORITATIVE_INFORMATION, "Non-Authoritative Information");
+    (204, NO_CONTENT, "No Content");
+    (205, RESET_CONTENT, "Reset Content");
+    (206, PARTIAL_CONTENT, "Partial Content");
+    (207, MULTI_STATUS, "Multi-Status");
+    (208, ALREADY_REPORTED, "Already Reported");
+    (226, IM_USED, "IM Used");
+
+    (300, MULTIPLE_CHOICES, "Multiple Choices");
+    (301, MOVED_PERMANENTLY, "Moved Permanently");
+    (302, FOUND, "Found");
+    (303, SEE_OTHER, "See Other");
+    (304, NOT_MODIFIED, "Not Modified");
+    (305, USE_PROXY, "Use Proxy");
+    (307, TEMPORARY_REDIRECT, "Temporary Redirect");
+    (308, PERMANENT_REDIRECT, "Permanent Redirect");
+
+    (400, BAD_REQUEST, "Bad Request");
+    (401, UNAUTHORIZED, "Unauthorized");
+    (402, PAYMENT_REQUIRED, "Payment Required");
+    (403, FORBIDDEN, "Forbidden");
+    (404, NOT_FOUND, "Not Found");
+    (405, METHOD_NOT_ALLOWED, "Method Not Allowed");
+    (406, NOT_ACCEPTABLE, "Not Acceptable");
+    (407, PROXY_AUTHENTICATION_REQUIRED, "Proxy Authentication Required");
+    (408, REQUEST_TIMEOUT, "Request Timeout");
+    (409, CONFLICT, "Conflict");
+    (410, GONE, "Gone");
+    (411, LENGTH_REQUIRED, "Length Required");
+    (412, PRECONDITION_FAILED, "Precondition Failed");
+    (413, PAYLOAD_TOO_LARGE, "Payload Too Large");
+    (414, URI_TOO_LONG, "URI Too Long");
+    (415, UNSUPPORTED_MEDIA_TYPE, "Unsupported Media Type");
+    (416, RANGE_NOT_SATISFIABLE, "Range Not Satisfiable");
+    (417, EXPECTATION_FAILED, "Expectation Failed");
+    (418, IM_A_TEAPOT, "I'm a teapot");
+    (421, MISDIRECTED_REQUEST, "Misdirected Request");
+    (422, UNPROCESSABLE_ENTITY, "Unprocessable Entity");
+    (423, LOCKED, "Locked");
+    (424, FAILED_DEPENDENCY, "Failed Dependency");
+    (425, TOO_EARLY, "Too Early");
+    (426, UPGRADE_REQUIRED, "Upgrade Required");
+    (428, PRECONDITION_REQUIRED, "Precondition Required");
+    (429, TOO_MANY_REQUESTS, "Too Many Requests");
+    (431, REQUEST_HEADER_FIELDS_TOO_LARGE, "Request Header Fields Too Large");
+    (451, UNAVAILABLE_FOR_LEGAL_REASONS, "Unavailable For Legal Reasons");
+
+    (500, INTERNAL_SERVER_ERROR, "Internal Server Error");
+    (501, NOT_IMPLEMENTED, "Not Implemented");
+    (502, BAD_GATEWAY, "Bad Gateway");
+    (503, SERVICE_UNAVAILABLE, "Service Unavailable");
+    (504, GATEWAY_TIMEOUT, "Gateway Timeout");
+    (505, HTTP_VERSION_NOT_SUPPORTED, "HTTP Version Not Supported");
+    (506, VARIANT_ALSO_NEGOTIATES, "Variant Also Negotiates");
+    (507, INSUFFICIENT_STORAGE, "Insufficient Storage");
+    (508, LOOP_DETECTED, "Loop Detected");
+    (510, NOT_EXTENDED, "Not Extended");
+    (511, NETWORK_AUTHENTICATION_REQUIRED, "Network Authentication Required");
+}
+
+impl StatusCode {
+    pub fn from_u16(code: u16) -> StatusCode {
+        StatusCode(code)
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// 1xx
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.0)
+    }
+
+    /// 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    /// 3xx
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    /// 4xx
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    /// 5xx
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.0)
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {}", self.0, reason),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 {
+        status.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_code() {
+        let status = StatusCode::from_u16(503);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status.as_u16(), 503);
+        assert_eq!(status.to_string(), "503 Service Unavailable");
+        assert!(status.is_server_error());
+        assert!(!status.is_success());
+
+        // 未定義のコードも数値のまま保持する
+        let status = StatusCode::from_u16(599);
+        assert_eq!(status.as_u16(), 599);
+        assert_eq!(status.canonical_reason(), None);
+        assert_eq!(status.to_string(), "599");
+        assert!(status.is_server_error());
+
+        assert!(StatusCode::CONTINUE.is_informational());
+        assert!(StatusCode::NO_CONTENT.is_success());
+        assert!(StatusCode::PERMANENT_REDIRECT.is_redirect());
+        assert!(StatusCode::TOO_MANY_REQUESTS.is_client_error());
+        assert_eq!(u16::from(StatusCode::NOT_FOUND), 404);
+    }
+}
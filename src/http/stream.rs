@@ -0,0 +1,136 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::{sleep, timeout, Sleep};
+use tokio_native_tls::TlsStream;
+
+use super::error::{HttpError, TimeoutKind};
+use super::tls::TlsConnectorBuilder;
+
+enum Inner {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+/// scheme に応じて平文の TCP か TLS を切り替えるためのストリーム
+pub struct HttpStream {
+    inner: Inner,
+    // 読み込みが止まってからエラーにするまでの時間
+    read_timeout: Option<Duration>,
+    read_deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl HttpStream {
+    /// host:port に接続する。`tls` が true の場合は TLS で包む。
+    /// 名前解決・TCP 接続・TLS ハンドシェイクはそれぞれ `connect_timeout` でタイムアウトする。
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        tls: bool,
+        connect_timeout: Duration,
+    ) -> Result<HttpStream, HttpError> {
+        let addrs = match timeout(connect_timeout, lookup_host((host, port))).await {
+            Ok(Ok(addrs)) => addrs.collect::<Vec<_>>(),
+            Ok(Err(_)) => return Err(HttpError::Dns(host.to_string())),
+            Err(_) => return Err(HttpError::Timeout(TimeoutKind::Connect)),
+        };
+        if addrs.is_empty() {
+            return Err(HttpError::Dns(host.to_string()));
+        }
+
+        let tcp_stream = match timeout(connect_timeout, TcpStream::connect(&addrs[..])).await {
+            Ok(Ok(tcp_stream)) => tcp_stream,
+            Ok(Err(why)) => return Err(HttpError::Connect(why)),
+            Err(_) => return Err(HttpError::Timeout(TimeoutKind::Connect)),
+        };
+
+        let inner = if tls {
+            let connector = match TlsConnectorBuilder::new() {
+                Ok(builder) => builder.connector,
+                Err(why) => return Err(HttpError::Tls(why.to_string())),
+            };
+            match timeout(connect_timeout, connector.connect(host, tcp_stream)).await {
+                Ok(Ok(tls_stream)) => Inner::Tls(Box::new(tls_stream)),
+                Ok(Err(why)) => return Err(HttpError::Tls(why.to_string())),
+                Err(_) => return Err(HttpError::Timeout(TimeoutKind::Connect)),
+            }
+        } else {
+            Inner::Plain(tcp_stream)
+        };
+
+        Ok(HttpStream {
+            inner,
+            read_timeout: None,
+            read_deadline: None,
+        })
+    }
+
+    pub fn set_read_timeout(&mut self, read_timeout: Duration) {
+        self.read_timeout = Some(read_timeout);
+        self.read_deadline = None;
+    }
+}
+
+impl AsyncRead for HttpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = match &mut this.inner {
+            Inner::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Inner::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        };
+
+        if poll.is_ready() {
+            this.read_deadline = None;
+            return poll;
+        }
+
+        // データが来ない間だけタイマーを進め、期限を過ぎたら TimedOut を返す
+        if let Some(read_timeout) = this.read_timeout {
+            let deadline = this
+                .read_deadline
+                .get_or_insert_with(|| Box::pin(sleep(read_timeout)));
+            if deadline.as_mut().poll(cx).is_ready() {
+                this.read_deadline = None;
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, "read timeout")));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for HttpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().inner {
+            Inner::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Inner::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().inner {
+            Inner::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Inner::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().inner {
+            Inner::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Inner::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
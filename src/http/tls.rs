@@ -6,12 +6,9 @@ pub struct TlsConnectorBuilder {
 }
 
 impl TlsConnectorBuilder {
-    pub fn new() -> Self {
-        Self {
-            connector: TlsConnector::from(match NativeTlsConnector::builder().build() {
-                Ok(tls_connector) => tls_connector,
-                Err(why) => panic!("tls connector error: {:?}", why),
-            }),
-        }
+    pub fn new() -> Result<Self, native_tls::Error> {
+        Ok(Self {
+            connector: TlsConnector::from(NativeTlsConnector::builder().build()?),
+        })
     }
 }
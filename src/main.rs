@@ -2,6 +2,7 @@ mod commands;
 mod handler;
 mod http;
 mod scheduler;
+mod store;
 mod url;
 mod utils;
 
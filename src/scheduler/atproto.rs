@@ -3,7 +3,9 @@ use std::error::Error;
 use serenity::{async_trait, client::Context, model::id::ChannelId};
 use tracing::{error, info};
 
-use crate::utils::{fetch_atproto::Feed, get_db_channel::get_db_channel};
+use crate::store::get_store;
+use crate::store::record::AtprotoLastDate;
+use crate::utils::fetch_atproto::Feed;
 
 use super::processer::Processer;
 
@@ -64,36 +66,18 @@ impl Processer<Feed> for ProcesserStruct {
     }
 
     async fn update_db_channel(&self, ctx: &Context) -> Result<(), Box<dyn Error>> {
-        let db_channel = match get_db_channel(&ctx).await {
-            Ok(db_channel) => db_channel,
+        let store = match get_store(ctx).await {
+            Ok(store) => store,
             Err(why) => {
-                error!("Error getting db channel: {:?}", why);
-                return Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "DBチャンネルが見つかりません。",
-                )));
+                error!("Error getting store: {:?}", why);
+                return Err(Box::new(why));
             }
         };
 
-        let messages = db_channel
-            .messages(&ctx.http, |retriever| retriever.limit(100))
-            .await
-            .unwrap()
-            .into_iter()
-            .filter(|message| message.content.starts_with("atproto_last_date"))
-            .collect::<Vec<_>>();
-
-        for message in messages {
-            let _ = message.delete(&ctx.http).await;
-        }
-
-        let now = chrono::Utc::now();
-        let now = now.format("%Y-%m-%d %H:%M:%S").to_string();
-        let _ = db_channel
-            .send_message(&ctx.http, |m| {
-                m.content(format!("atproto_last_date {}", now))
-            })
-            .await;
+        let last_date = AtprotoLastDate {
+            date: chrono::Utc::now().naive_utc(),
+        };
+        store.set(&last_date).await?;
         Ok(())
     }
 
@@ -1,3 +1,4 @@
 pub mod atproto;
 pub mod processer;
 pub mod rss;
+pub mod todo;
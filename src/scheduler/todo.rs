@@ -0,0 +1,313 @@
+use std::env;
+use std::error::Error;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use serenity::{
+    async_trait,
+    client::Context,
+    model::id::{ChannelId, UserId},
+};
+use tracing::{error, info, warn};
+
+use crate::commands::todo::list::truncate;
+use crate::store::get_store;
+use crate::store::record::{Todo, TodoReminder, TodoScope, TodoStatus};
+use crate::store::Stored;
+
+use super::processer::Processer;
+
+// todo の期限は日本時間で入力されている
+const TIMEZONE_OFFSET_HOURS: i64 = 9;
+// 期限の何分前に通知するか。RINTON_TODO_REMIND_BEFORE_MINUTES で変えられる
+const DEFAULT_LEAD_MINUTES: i64 = 24 * 60;
+// 通知に入れるタイトルの長さ。Discord のメッセージは 2000 文字まで
+const TITLE_LENGTH: usize = 300;
+
+/// 送る通知と対象の todo
+pub(crate) struct Reminder {
+    todo: Stored<Todo>,
+    kind: TodoReminder,
+}
+
+pub(crate) struct ProcesserStruct;
+
+fn lead_time() -> Duration {
+    parse_lead_time(
+        env::var("RINTON_TODO_REMIND_BEFORE_MINUTES")
+            .ok()
+            .as_deref(),
+    )
+}
+
+/// 分数を読む。読めない値や負の値、大きすぎる値の場合は DEFAULT_LEAD_MINUTES にする
+fn parse_lead_time(minutes: Option<&str>) -> Duration {
+    minutes
+        .and_then(|minutes| minutes.trim().parse::<i64>().ok())
+        .filter(|minutes| *minutes >= 0)
+        .and_then(Duration::try_minutes)
+        .unwrap_or_else(|| Duration::minutes(DEFAULT_LEAD_MINUTES))
+}
+
+/// 今の日本時間
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc() + Duration::hours(TIMEZONE_OFFSET_HOURS)
+}
+
+/// now の時点で送るべき通知を返す。期限を過ぎている場合は期限前の通知は送らない
+fn reminder(todo: &Todo, now: NaiveDateTime, lead: Duration) -> Option<TodoReminder> {
+    if todo.status == TodoStatus::Done {
+        return None;
+    }
+    let due = todo.due?;
+    let kind = if now >= due {
+        TodoReminder::Overdue
+    } else if due
+        .checked_sub_signed(lead)
+        .is_none_or(|start| now >= start)
+    {
+        TodoReminder::Upcoming
+    } else {
+        return None;
+    };
+    if todo.reminded.contains(&kind) {
+        None
+    } else {
+        Some(kind)
+    }
+}
+
+/// 通知の送り先
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Destination {
+    Channel(u64),
+    /// 個人の todo はチャンネルに送らず DM で知らせる
+    Direct(u64),
+}
+
+fn destination(todo: &Todo) -> Option<Destination> {
+    match todo.scope {
+        TodoScope::User(_) => todo.assignee.or(todo.created_by).map(Destination::Direct),
+        _ => todo.channel.map(Destination::Channel),
+    }
+}
+
+fn format_reminder(todo: &Todo, kind: TodoReminder) -> String {
+    // 担当者がいなければ作った人に知らせる
+    let mention = match todo.assignee.or(todo.created_by) {
+        Some(user) => format!("<@{}> ", user),
+        None => "".to_string(),
+    };
+    let due = todo
+        .due
+        .map(|due| due.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    let title = truncate(&todo.title, TITLE_LENGTH);
+    match kind {
+        TodoReminder::Upcoming => format!(
+            "{}TODO {}: {} の期限 ({}) が近づいています。",
+            mention, todo.id, title, due
+        ),
+        TodoReminder::Overdue => format!(
+            "{}TODO {}: {} の期限 ({}) を過ぎました。",
+            mention, todo.id, title, due
+        ),
+    }
+}
+
+#[async_trait]
+impl Processer<Reminder> for ProcesserStruct {
+    async fn fetch(&self, ctx: &Context) -> Result<Vec<Reminder>, Box<dyn Error>> {
+        let store = get_store(ctx).await?;
+        let now = now();
+        let lead = lead_time();
+        let reminders = store
+            .list::<Todo>()
+            .await?
+            .into_iter()
+            .filter_map(|todo| {
+                let kind = reminder(&todo.record, now, lead)?;
+                Some(Reminder { todo, kind })
+            })
+            .collect();
+        Ok(reminders)
+    }
+
+    async fn post_to_channel(
+        &self,
+        ctx: &Context,
+        items: Vec<Reminder>,
+    ) -> Result<(), Box<dyn Error>> {
+        let store = get_store(ctx).await?;
+        for Reminder { todo, kind } in items {
+            let channel = match destination(&todo.record) {
+                Some(Destination::Channel(channel)) => ChannelId(channel),
+                Some(Destination::Direct(user)) => {
+                    match UserId(user).create_dm_channel(&ctx.http).await {
+                        Ok(channel) => channel.id,
+                        Err(why) => {
+                            error!("Error creating DM channel: {:?}", why);
+                            continue;
+                        }
+                    }
+                }
+                None => {
+                    warn!("No channel found for todo: {}", todo.record.id);
+                    continue;
+                }
+            };
+            let content = format_reminder(&todo.record, kind);
+            if let Err(why) = channel.say(&ctx.http, content).await {
+                error!("Error sending todo reminder: {:?}", why);
+                continue;
+            }
+
+            // 同じ通知を二度送らないように、送ったらすぐに記録する
+            let mut record = todo.record;
+            record.reminded.push(kind);
+            if let Err(why) = store.update_record(todo.id, &record).await {
+                error!("Error saving todo reminder: {:?}", why);
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_db_channel(&self, _ctx: &Context) -> Result<(), Box<dyn Error>> {
+        // 送った通知は post_to_channel で todo ごとに記録している
+        Ok(())
+    }
+
+    async fn run(&self, ctx: &Context) -> Result<(), Box<dyn Error>> {
+        info!("todo reminder is started.");
+
+        let reminders = self.fetch(ctx).await?;
+        self.post_to_channel(ctx, reminders).await?;
+        self.update_db_channel(ctx).await?;
+
+        info!("todo reminder is done.");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_reminder() {
+        let lead = Duration::hours(24);
+        let todo = Todo {
+            due: Some(date("2024-01-10 18:00")),
+            ..Todo::new(1, "release")
+        };
+
+        assert_eq!(reminder(&todo, date("2024-01-09 17:59"), lead), None);
+        assert_eq!(
+            reminder(&todo, date("2024-01-09 18:00"), lead),
+            Some(TodoReminder::Upcoming)
+        );
+        assert_eq!(
+            reminder(&todo, date("2024-01-10 18:00"), lead),
+            Some(TodoReminder::Overdue)
+        );
+
+        // 送った通知は送らない
+        let reminded = Todo {
+            reminded: vec![TodoReminder::Upcoming],
+            ..todo.clone()
+        };
+        assert_eq!(reminder(&reminded, date("2024-01-10 12:00"), lead), None);
+        assert_eq!(
+            reminder(&reminded, date("2024-01-11 00:00"), lead),
+            Some(TodoReminder::Overdue)
+        );
+
+        // 終わった todo と期限のない todo は通知しない
+        let done = Todo {
+            status: TodoStatus::Done,
+            ..todo.clone()
+        };
+        assert_eq!(reminder(&done, date("2024-01-11 00:00"), lead), None);
+        assert_eq!(
+            reminder(&Todo::new(2, "someday"), date("2024-01-11 00:00"), lead),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_lead_time() {
+        assert_eq!(parse_lead_time(Some("30")), Duration::minutes(30));
+        assert_eq!(parse_lead_time(Some("0")), Duration::zero());
+        let default = Duration::minutes(DEFAULT_LEAD_MINUTES);
+        assert_eq!(parse_lead_time(None), default);
+        assert_eq!(parse_lead_time(Some("soon")), default);
+        assert_eq!(parse_lead_time(Some("-30")), default);
+        assert_eq!(parse_lead_time(Some("9223372036854775807")), default);
+
+        // 期限から引くとあふれるほど長くても panic しない
+        let todo = Todo {
+            due: Some(date("2024-01-10 18:00")),
+            ..Todo::new(1, "release")
+        };
+        let lead = Duration::try_minutes(i64::MAX / 60_000).unwrap();
+        assert_eq!(
+            reminder(&todo, date("2024-01-01 00:00"), lead),
+            Some(TodoReminder::Upcoming)
+        );
+    }
+
+    #[test]
+    fn test_destination() {
+        let todo = Todo {
+            assignee: Some(1234),
+            created_by: Some(5678),
+            channel: Some(42),
+            scope: TodoScope::Guild(1),
+            ..Todo::new(1, "release")
+        };
+        assert_eq!(destination(&todo), Some(Destination::Channel(42)));
+
+        // 個人の todo はチャンネルに送らない
+        let private = Todo {
+            scope: TodoScope::User(5678),
+            ..todo.clone()
+        };
+        assert_eq!(destination(&private), Some(Destination::Direct(1234)));
+        let private = Todo {
+            assignee: None,
+            ..private
+        };
+        assert_eq!(destination(&private), Some(Destination::Direct(5678)));
+        let private = Todo {
+            created_by: None,
+            ..private
+        };
+        assert_eq!(destination(&private), None);
+    }
+
+    #[test]
+    fn test_format_reminder() {
+        let todo = Todo {
+            due: Some(date("2024-01-10 18:00")),
+            assignee: Some(1234),
+            created_by: Some(5678),
+            ..Todo::new(1, "release")
+        };
+        assert_eq!(
+            format_reminder(&todo, TodoReminder::Overdue),
+            "<@1234> TODO 1: release の期限 (2024-01-10 18:00) を過ぎました。"
+        );
+
+        // 長いタイトルは切り詰めて Discord の上限に収める
+        let long = Todo {
+            title: "a".repeat(6000),
+            ..todo
+        };
+        let content = format_reminder(&long, TodoReminder::Upcoming);
+        assert!(content.chars().count() <= 2000);
+    }
+}
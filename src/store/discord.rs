@@ -0,0 +1,275 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use serenity::async_trait;
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, MessageId};
+
+use super::error::StoreError;
+use super::Store;
+use crate::utils::get_db_channel::get_db_channel;
+
+// Discord のメッセージの文字数の上限
+const MESSAGE_LIMIT: usize = 2000;
+// 1 回の API で取得できるメッセージの数の上限
+const PAGE_SIZE: u64 = 100;
+
+// チャンネルのメッセージの (ID, 本文)。新しい順
+type Messages = Arc<Vec<(u64, String)>>;
+
+/// 読み込んだメッセージのキャッシュ。
+/// 読み込んでいる間に書き込まれた場合は古い内容なので、捨てられた回数 (generation) が変わっていたら保存しない
+#[derive(Default)]
+struct MessageCache {
+    generation: u64,
+    messages: Option<Messages>,
+}
+
+impl MessageCache {
+    fn store(&mut self, generation: u64, messages: Messages) {
+        if self.generation == generation {
+            self.messages = Some(messages);
+        }
+    }
+
+    fn invalidate(&mut self) {
+        self.generation += 1;
+        self.messages = None;
+    }
+}
+
+/// #db チャンネルに `<prefix> <payload>` という形式のメッセージとして保存する。
+/// record の id はメッセージの ID。
+/// 読むたびにチャンネル全体を取得しないように、メッセージはメモリにキャッシュして書き込んだときに捨てる
+pub struct DiscordStore {
+    http: Arc<Http>,
+    channel: ChannelId,
+    cache: Mutex<MessageCache>,
+}
+
+impl DiscordStore {
+    pub async fn open(ctx: &Context) -> Result<DiscordStore, StoreError> {
+        let channel = get_db_channel(ctx)
+            .await
+            .map_err(|why| StoreError::Unavailable(why.to_string()))?;
+        Ok(DiscordStore {
+            http: ctx.http.clone(),
+            channel: channel.id,
+            cache: Mutex::new(MessageCache::default()),
+        })
+    }
+
+    /// チャンネルの全てのメッセージの (ID, 本文) を新しい順に返す
+    async fn messages(&self) -> Result<Messages, StoreError> {
+        let generation = {
+            let cache = self.lock_cache();
+            if let Some(messages) = &cache.messages {
+                return Ok(messages.clone());
+            }
+            cache.generation
+        };
+
+        let messages = Arc::new(
+            read_pages(|before| async move {
+                let page = self
+                    .channel
+                    .messages(&self.http, |retriever| {
+                        let retriever = retriever.limit(PAGE_SIZE);
+                        match before {
+                            Some(id) => retriever.before(MessageId(id)),
+                            None => retriever,
+                        }
+                    })
+                    .await?;
+                Ok(page
+                    .into_iter()
+                    .map(|message| (message.id.0, message.content))
+                    .collect())
+            })
+            .await?,
+        );
+        self.lock_cache().store(generation, messages.clone());
+        Ok(messages)
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, MessageCache> {
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn invalidate(&self) {
+        self.lock_cache().invalidate();
+    }
+}
+
+/// before に渡したメッセージより古いページを順に取得して、最後のページまで繋げる。
+/// ページは新しい順に並んでいるので、最後のメッセージの ID を次の before にする
+async fn read_pages<F, Fut>(mut fetch: F) -> Result<Vec<(u64, String)>, StoreError>
+where
+    F: FnMut(Option<u64>) -> Fut,
+    Fut: Future<Output = Result<Vec<(u64, String)>, StoreError>>,
+{
+    let mut messages = Vec::new();
+    let mut before = None;
+    loop {
+        let page = fetch(before).await?;
+        let length = page.len() as u64;
+        before = page.last().map(|(id, _)| *id);
+        messages.extend(page);
+        if length < PAGE_SIZE || before.is_none() {
+            return Ok(messages);
+        }
+    }
+}
+
+fn content(prefix: &str, payload: &str) -> Result<String, StoreError> {
+    let content = format!("{} {}", prefix, payload);
+    let length = content.chars().count();
+    if length > MESSAGE_LIMIT {
+        return Err(StoreError::TooLarge(length));
+    }
+    Ok(content)
+}
+
+/// メッセージが prefix の record なら payload の部分を返す
+fn payload<'a>(content: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = content.strip_prefix(prefix)?;
+    if rest.is_empty() || rest.starts_with(' ') {
+        Some(rest.trim_start())
+    } else {
+        // rss_link と rss_linkx のように prefix が途中で切れている場合は別の種類
+        None
+    }
+}
+
+#[async_trait]
+impl Store for DiscordStore {
+    async fn load(&self, prefix: &str) -> Result<Vec<(u64, String)>, StoreError> {
+        let records = self
+            .messages()
+            .await?
+            .iter()
+            .filter_map(|(id, content)| {
+                payload(content, prefix).map(|payload| (*id, payload.to_string()))
+            })
+            .collect();
+        Ok(records)
+    }
+
+    async fn insert(&self, prefix: &str, payload: String) -> Result<u64, StoreError> {
+        let content = content(prefix, &payload)?;
+        let message = self.channel.say(&self.http, content).await;
+        // 書き込みに失敗しても途中まで反映されているかもしれないので、結果によらずキャッシュを捨てる
+        self.invalidate();
+        Ok(message?.id.0)
+    }
+
+    async fn update(&self, prefix: &str, id: u64, payload: String) -> Result<(), StoreError> {
+        let content = content(prefix, &payload)?;
+        let result = self
+            .channel
+            .edit_message(&self.http, MessageId(id), |m| m.content(content))
+            .await;
+        self.invalidate();
+        result?;
+        Ok(())
+    }
+
+    async fn delete(&self, _prefix: &str, id: u64) -> Result<(), StoreError> {
+        let result = self.channel.delete_message(&self.http, MessageId(id)).await;
+        self.invalidate();
+        result?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        assert_eq!(payload("todo_message 3 foo", "todo_message"), Some("3 foo"));
+        assert_eq!(
+            payload(r#"rss_link {"link":"https://example.com"}"#, "rss_link"),
+            Some(r#"{"link":"https://example.com"}"#)
+        );
+        assert_eq!(payload("rss_last_date 2024-01-01", "rss_link"), None);
+        assert_eq!(payload("rss_linkx foo", "rss_link"), None);
+        assert_eq!(payload("todo_message", "todo_message"), Some(""));
+    }
+
+    #[test]
+    fn test_content() {
+        assert_eq!(
+            content("rss_link", "https://example.com").unwrap(),
+            "rss_link https://example.com"
+        );
+        assert!(matches!(
+            content("todo_message", &"a".repeat(2000)),
+            Err(StoreError::TooLarge(2013))
+        ));
+    }
+
+    #[test]
+    fn test_message_cache() {
+        let old: Messages = Arc::new(vec![(1, "todo_message 1 old".to_string())]);
+        let new: Messages = Arc::new(vec![(2, "todo_message 1 new".to_string())]);
+
+        let mut cache = MessageCache::default();
+        cache.store(cache.generation, old.clone());
+        assert_eq!(cache.messages, Some(old.clone()));
+
+        // 読み込みを始めた後に書き込まれたので、読み込んだ古い内容は保存しない
+        cache.invalidate();
+        let generation = cache.generation;
+        cache.invalidate();
+        cache.store(generation, old);
+        assert_eq!(cache.messages, None);
+
+        cache.store(cache.generation, new.clone());
+        assert_eq!(cache.messages, Some(new));
+    }
+
+    #[tokio::test]
+    async fn test_read_pages() {
+        // ID が 250 から 1 までの 250 件のメッセージがあるチャンネル
+        let mut cursors = Vec::new();
+        let messages = read_pages(|before| {
+            cursors.push(before);
+            let newest = before.map_or(250, |id| id - 1);
+            let page = (1..=newest)
+                .rev()
+                .take(PAGE_SIZE as usize)
+                .map(|id| (id, format!("todo_message {}", id)))
+                .collect();
+            async move { Ok(page) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(cursors, vec![None, Some(151), Some(51)]);
+        assert_eq!(messages.len(), 250);
+        assert_eq!(messages.first().unwrap().0, 250);
+        assert_eq!(messages.last().unwrap().0, 1);
+
+        // ちょうど 100 件の場合は空のページで終わる
+        let mut calls = 0;
+        let messages = read_pages(|before| {
+            calls += 1;
+            let page = match before {
+                None => (1..=PAGE_SIZE)
+                    .rev()
+                    .map(|id| (id, String::new()))
+                    .collect(),
+                Some(_) => Vec::new(),
+            };
+            async move { Ok(page) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(messages.len(), 100);
+        assert_eq!(calls, 2);
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// store の読み書きで発生するエラー
+#[derive(Debug)]
+pub enum StoreError {
+    /// 保存先が見つからない、もしくは設定が不正
+    Unavailable(String),
+    /// Discord の API の呼び出しに失敗した
+    Discord(Box<serenity::Error>),
+    /// SQLite の読み書きに失敗した
+    Sqlite(rusqlite::Error),
+    /// record を JSON にできなかった
+    Serialize(serde_json::Error),
+    /// 1 件の record が保存できる大きさを超えている
+    TooLarge(usize),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Unavailable(why) => write!(f, "store is unavailable: {}", why),
+            StoreError::Discord(why) => write!(f, "discord error: {}", why),
+            StoreError::Sqlite(why) => write!(f, "sqlite error: {}", why),
+            StoreError::Serialize(why) => write!(f, "failed to serialize record: {}", why),
+            StoreError::TooLarge(length) => write!(f, "record is too large: {} chars", length),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<serenity::Error> for StoreError {
+    fn from(why: serenity::Error) -> Self {
+        StoreError::Discord(Box::new(why))
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(why: rusqlite::Error) -> Self {
+        StoreError::Sqlite(why)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(why: serde_json::Error) -> Self {
+        StoreError::Serialize(why)
+    }
+}
@@ -0,0 +1,264 @@
+pub mod discord;
+pub mod error;
+pub mod record;
+pub mod sqlite;
+
+use std::env;
+use std::sync::Arc;
+
+use serenity::async_trait;
+use serenity::prelude::{Context, TypeMapKey};
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+
+use discord::DiscordStore;
+use error::StoreError;
+use record::Record;
+use sqlite::SqliteStore;
+
+// db チャンネルから SQLite への移行を記録する名前
+const DISCORD_MIGRATION: &str = "discord_db_channel";
+
+/// 保存されている 1 件の record。id は保存先ごとの識別子 (Discord ならメッセージの ID)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stored<R> {
+    pub id: u64,
+    pub record: R,
+}
+
+/// todo や RSS の登録、scheduler の取得日時などの保存先。
+/// record の種類 (prefix) ごとに payload の文字列を読み書きする。
+/// 型つきで読み書きする場合は `dyn Store` の list や insert_record を使う
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// prefix の record を全て返す
+    async fn load(&self, prefix: &str) -> Result<Vec<(u64, String)>, StoreError>;
+    /// record を追加して id を返す
+    async fn insert(&self, prefix: &str, payload: String) -> Result<u64, StoreError>;
+    async fn update(&self, prefix: &str, id: u64, payload: String) -> Result<(), StoreError>;
+    async fn delete(&self, prefix: &str, id: u64) -> Result<(), StoreError>;
+
+    /// prefix の record を全て消して payload だけにする。トランザクションが使える保存先では上書きする
+    async fn replace(&self, prefix: &str, payload: String) -> Result<u64, StoreError> {
+        for (id, _) in self.load(prefix).await? {
+            self.delete(prefix, id).await?;
+        }
+        self.insert(prefix, payload).await
+    }
+}
+
+impl dyn Store {
+    /// R の record を全て返す。読めない record は warn を出して読み飛ばす
+    pub async fn list<R: Record>(&self) -> Result<Vec<Stored<R>>, StoreError> {
+        let records = self
+            .load(R::PREFIX)
+            .await?
+            .into_iter()
+            .filter_map(|(id, payload)| match record::decode(&payload) {
+                Some(record) => Some(Stored { id, record }),
+                None => {
+                    warn!("invalid {} record {}: {}", R::PREFIX, id, payload);
+                    None
+                }
+            })
+            .collect();
+        Ok(records)
+    }
+
+    pub async fn insert_record<R: Record>(&self, record: &R) -> Result<u64, StoreError> {
+        self.insert(R::PREFIX, record::encode(record)?).await
+    }
+
+    pub async fn update_record<R: Record>(&self, id: u64, record: &R) -> Result<(), StoreError> {
+        self.update(R::PREFIX, id, record::encode(record)?).await
+    }
+
+    pub async fn delete_record<R: Record>(&self, id: u64) -> Result<(), StoreError> {
+        self.delete(R::PREFIX, id).await
+    }
+
+    /// 1 件だけ保存する種類の record (最後に取得した日時など) を返す
+    pub async fn get<R: Record>(&self) -> Result<Option<R>, StoreError> {
+        let records = self.list::<R>().await?;
+        Ok(records.into_iter().next().map(|stored| stored.record))
+    }
+
+    /// 1 件だけ保存する種類の record を置き換える
+    pub async fn set<R: Record>(&self, record: &R) -> Result<(), StoreError> {
+        self.replace(R::PREFIX, record::encode(record)?).await?;
+        Ok(())
+    }
+}
+
+struct StoreKey;
+
+impl TypeMapKey for StoreKey {
+    // scheduler とコマンドが同時に初めて呼んでも store が 1 つだけ作られるようにする
+    type Value = Arc<OnceCell<Arc<dyn Store>>>;
+}
+
+/// bot 全体で共有する store を返す。初めて呼ばれたときに作る
+pub async fn get_store(ctx: &Context) -> Result<Arc<dyn Store>, StoreError> {
+    // ctx.data のロックは cell を取り出す間だけ持ち、store を作る間は cell で待たせる
+    let cell = ctx
+        .data
+        .write()
+        .await
+        .entry::<StoreKey>()
+        .or_insert_with(|| Arc::new(OnceCell::new()))
+        .clone();
+    let store = cell.get_or_try_init(|| open_store(ctx)).await?;
+    Ok(store.clone())
+}
+
+async fn open_store(ctx: &Context) -> Result<Arc<dyn Store>, StoreError> {
+    // RINTON_STORE=sqlite ならローカルの SQLite に保存する。それ以外は #db チャンネル
+    let store: Arc<dyn Store> = match env::var("RINTON_STORE").as_deref() {
+        Ok("sqlite") => {
+            let path = env::var("RINTON_SQLITE_PATH").unwrap_or_else(|_| "rinton.db".to_string());
+            let store = SqliteStore::open(&path)?;
+            migrate_from_discord(ctx, &store).await?;
+            Arc::new(store)
+        }
+        _ => Arc::new(DiscordStore::open(ctx).await?),
+    };
+    Ok(store)
+}
+
+/// #db チャンネルのメッセージを SQLite に取り込む。一度取り込んだら以降は何もしない
+async fn migrate_from_discord(ctx: &Context, store: &SqliteStore) -> Result<(), StoreError> {
+    // 移行済みなら db チャンネルを読まない。取り込むかどうかは import の中で改めて確かめる
+    if store.is_migrated(DISCORD_MIGRATION)? {
+        return Ok(());
+    }
+
+    let discord = match DiscordStore::open(ctx).await {
+        Ok(discord) => discord,
+        Err(why) => {
+            // db チャンネルがなければ移行するものもないので、次に起動したときにもう一度試す
+            warn!("skip migration from db channel: {}", why);
+            return Ok(());
+        }
+    };
+
+    let mut records = Vec::new();
+    for prefix in record::PREFIXES {
+        // Discord では新しいメッセージから返ってくるので、古い順にして id の順番を合わせる
+        let mut loaded = discord.load(prefix).await?;
+        loaded.sort_by_key(|(id, _)| *id);
+        records.extend(
+            loaded
+                .into_iter()
+                .map(|(_, payload)| (prefix.to_string(), payload)),
+        );
+    }
+    if store.import(DISCORD_MIGRATION, &records)? {
+        info!("migrated {} records from db channel", records.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    use super::record::{RssLastDate, Todo};
+    use super::*;
+
+    // テスト用にメモリ上に保存する store
+    #[derive(Default)]
+    struct MemoryStore {
+        records: Mutex<BTreeMap<u64, (String, String)>>,
+    }
+
+    #[async_trait]
+    impl Store for MemoryStore {
+        async fn load(&self, prefix: &str) -> Result<Vec<(u64, String)>, StoreError> {
+            let records = self.records.lock().unwrap();
+            Ok(records
+                .iter()
+                .filter(|(_, (record_prefix, _))| record_prefix == prefix)
+                .map(|(id, (_, payload))| (*id, payload.clone()))
+                .collect())
+        }
+
+        async fn insert(&self, prefix: &str, payload: String) -> Result<u64, StoreError> {
+            let mut records = self.records.lock().unwrap();
+            let id = records.keys().next_back().map_or(1, |id| id + 1);
+            records.insert(id, (prefix.to_string(), payload));
+            Ok(id)
+        }
+
+        async fn update(&self, prefix: &str, id: u64, payload: String) -> Result<(), StoreError> {
+            let mut records = self.records.lock().unwrap();
+            records.insert(id, (prefix.to_string(), payload));
+            Ok(())
+        }
+
+        async fn delete(&self, _prefix: &str, id: u64) -> Result<(), StoreError> {
+            self.records.lock().unwrap().remove(&id);
+            Ok(())
+        }
+    }
+
+    fn todo(id: u64, title: &str) -> Todo {
+        Todo::new(id, title)
+    }
+
+    #[tokio::test]
+    async fn test_typed_records() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        // 以前の形式のメッセージと壊れたメッセージが混ざっていても読める
+        store
+            .insert("todo_message", "1 legacy todo".to_string())
+            .await
+            .unwrap();
+        store
+            .insert("todo_message", "broken".to_string())
+            .await
+            .unwrap();
+        let id = store.insert_record(&todo(2, "new todo")).await.unwrap();
+
+        let todos = store.list::<Todo>().await.unwrap();
+        assert_eq!(
+            todos,
+            vec![
+                Stored {
+                    id: 1,
+                    record: todo(1, "legacy todo")
+                },
+                Stored {
+                    id,
+                    record: todo(2, "new todo")
+                },
+            ]
+        );
+
+        store.update_record(id, &todo(2, "edited")).await.unwrap();
+        store.delete_record::<Todo>(1).await.unwrap();
+        let todos = store.list::<Todo>().await.unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].record, todo(2, "edited"));
+    }
+
+    #[tokio::test]
+    async fn test_single_record() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        assert_eq!(store.get::<RssLastDate>().await.unwrap(), None);
+
+        store
+            .insert("rss_last_date", "2024-01-01 00:00:00".to_string())
+            .await
+            .unwrap();
+        let last = store.get::<RssLastDate>().await.unwrap().unwrap();
+        assert_eq!(last.date.to_string(), "2024-01-01 00:00:00");
+
+        let next = RssLastDate {
+            date: last.date + chrono::Duration::hours(1),
+        };
+        store.set(&next).await.unwrap();
+        assert_eq!(store.load("rss_last_date").await.unwrap().len(), 1);
+        assert_eq!(store.get::<RssLastDate>().await.unwrap(), Some(next));
+    }
+}
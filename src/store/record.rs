@@ -0,0 +1,333 @@
+use chrono::NaiveDateTime;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// store に保存する値の種類。payload は JSON で保存する
+pub trait Record: Serialize + DeserializeOwned + Send + Sync {
+    /// 種類ごとの prefix。Discord の db チャンネルでは以前からこの prefix で始まるメッセージとして保存している
+    const PREFIX: &'static str;
+
+    /// JSON にする前のスペース区切りの形式 (`todo_message 3 foo` の `3 foo` の部分) を読む
+    fn parse_legacy(body: &str) -> Option<Self>;
+}
+
+/// payload を record にする。JSON でなければ以前の形式として読む
+pub fn decode<R: Record>(payload: &str) -> Option<R> {
+    if payload.starts_with('{') {
+        serde_json::from_str(payload).ok()
+    } else {
+        R::parse_legacy(payload)
+    }
+}
+
+/// 保存している record の種類の prefix。Discord の db チャンネルから移行するときに使う
+pub const PREFIXES: [&str; 4] = [
+    Todo::PREFIX,
+    RssLink::PREFIX,
+    RssLastDate::PREFIX,
+    AtprotoLastDate::PREFIX,
+];
+
+pub fn encode<R: Record>(record: &R) -> Result<String, serde_json::Error> {
+    serde_json::to_string(record)
+}
+
+/// todo の進み具合
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TodoStatus {
+    #[default]
+    Open,
+    Doing,
+    Done,
+}
+
+impl TodoStatus {
+    pub const ALL: [TodoStatus; 3] = [TodoStatus::Open, TodoStatus::Doing, TodoStatus::Done];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TodoStatus::Open => "open",
+            TodoStatus::Doing => "doing",
+            TodoStatus::Done => "done",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<TodoStatus> {
+        TodoStatus::ALL
+            .iter()
+            .copied()
+            .find(|status| status.name() == name)
+    }
+}
+
+/// todo が見える範囲。値はそれぞれの Discord の ID
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TodoScope {
+    /// 範囲を選べるようになる前の todo。誰からも見える
+    #[default]
+    Global,
+    User(u64),
+    Channel(u64),
+    Guild(u64),
+}
+
+impl TodoScope {
+    /// user が channel (guild が None なら DM) から見られるか
+    pub fn is_visible(self, user: u64, channel: u64, guild: Option<u64>) -> bool {
+        match self {
+            TodoScope::Global => true,
+            TodoScope::User(id) => id == user,
+            TodoScope::Channel(id) => id == channel,
+            TodoScope::Guild(id) => Some(id) == guild,
+        }
+    }
+}
+
+/// 期限の通知の種類
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TodoReminder {
+    /// 期限が近づいた
+    Upcoming,
+    /// 期限を過ぎた
+    Overdue,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Todo {
+    pub id: u64,
+    // 以前は message という名前で保存していた
+    #[serde(alias = "message")]
+    pub title: String,
+    /// 期限。入力されたままの日本時間の日時
+    #[serde(default, with = "optional_date_format")]
+    pub due: Option<NaiveDateTime>,
+    /// 担当者の Discord のユーザー ID
+    #[serde(default)]
+    pub assignee: Option<u64>,
+    #[serde(default)]
+    pub status: TodoStatus,
+    /// 作成した Discord のユーザー ID。以前の形式の todo にはない
+    #[serde(default)]
+    pub created_by: Option<u64>,
+    #[serde(default)]
+    pub scope: TodoScope,
+    /// 作成したチャンネルの ID。期限の通知はここに送る
+    #[serde(default)]
+    pub channel: Option<u64>,
+    /// 送った期限の通知。期限を変えたら空にする
+    #[serde(default)]
+    pub reminded: Vec<TodoReminder>,
+}
+
+impl Todo {
+    pub fn new(id: u64, title: &str) -> Todo {
+        Todo {
+            id,
+            title: title.to_string(),
+            due: None,
+            assignee: None,
+            status: TodoStatus::Open,
+            created_by: None,
+            scope: TodoScope::Global,
+            channel: None,
+            reminded: Vec::new(),
+        }
+    }
+}
+
+impl Record for Todo {
+    const PREFIX: &'static str = "todo_message";
+
+    // 3 foo bar
+    fn parse_legacy(body: &str) -> Option<Self> {
+        let mut parts = body.splitn(2, ' ');
+        let id = parts.next()?.parse().ok()?;
+        let title = parts.next().unwrap_or("").trim();
+        Some(Todo::new(id, title))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RssLink {
+    pub link: String,
+}
+
+impl Record for RssLink {
+    const PREFIX: &'static str = "rss_link";
+
+    fn parse_legacy(body: &str) -> Option<Self> {
+        let link = body.split_whitespace().next()?;
+        Some(RssLink {
+            link: link.to_string(),
+        })
+    }
+}
+
+// 日時は以前の形式と同じ %Y-%m-%d %H:%M:%S (UTC) の文字列で保存する
+mod date_format {
+    use chrono::NaiveDateTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+    pub fn serialize<S: Serializer>(
+        date: &NaiveDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&date.format(FORMAT).to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDateTime, D::Error> {
+        let date = String::deserialize(deserializer)?;
+        NaiveDateTime::parse_from_str(&date, FORMAT).map_err(serde::de::Error::custom)
+    }
+}
+
+mod optional_date_format {
+    use chrono::NaiveDateTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::date_format::FORMAT;
+
+    pub fn serialize<S: Serializer>(
+        date: &Option<NaiveDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match date {
+            Some(date) => serializer.serialize_str(&date.format(FORMAT).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<NaiveDateTime>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(date) => NaiveDateTime::parse_from_str(&date, FORMAT)
+                .map(Some)
+                .map_err(serde::de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+/// RSS を最後に取得した日時
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RssLastDate {
+    #[serde(with = "date_format")]
+    pub date: NaiveDateTime,
+}
+
+impl Record for RssLastDate {
+    const PREFIX: &'static str = "rss_last_date";
+
+    // 2024-01-01 00:00:00
+    fn parse_legacy(body: &str) -> Option<Self> {
+        let date = NaiveDateTime::parse_from_str(body.trim(), date_format::FORMAT).ok()?;
+        Some(RssLastDate { date })
+    }
+}
+
+/// 部分ツイートを最後に取得した日時
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct AtprotoLastDate {
+    #[serde(with = "date_format")]
+    pub date: NaiveDateTime,
+}
+
+impl Record for AtprotoLastDate {
+    const PREFIX: &'static str = "atproto_last_date";
+
+    fn parse_legacy(body: &str) -> Option<Self> {
+        let date = NaiveDateTime::parse_from_str(body.trim(), date_format::FORMAT).ok()?;
+        Some(AtprotoLastDate { date })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_legacy() {
+        assert_eq!(
+            decode::<Todo>("3 牛乳を買う"),
+            Some(Todo::new(3, "牛乳を買う"))
+        );
+        assert_eq!(
+            decode::<Todo>("4 wash the dishes"),
+            Some(Todo::new(4, "wash the dishes"))
+        );
+        // 項目が増える前の JSON
+        assert_eq!(
+            decode::<Todo>(r#"{"id":5,"message":"old json"}"#),
+            Some(Todo::new(5, "old json"))
+        );
+        assert_eq!(decode::<Todo>("foo"), None);
+        assert_eq!(
+            decode::<RssLink>("https://example.com/feed"),
+            Some(RssLink {
+                link: "https://example.com/feed".to_string()
+            })
+        );
+        assert_eq!(
+            decode::<RssLastDate>("2024-01-02 03:04:05").map(|last| last.date.to_string()),
+            Some("2024-01-02 03:04:05".to_string())
+        );
+        assert_eq!(decode::<AtprotoLastDate>("2024-01-02"), None);
+    }
+
+    #[test]
+    fn test_scope() {
+        assert!(TodoScope::Global.is_visible(1, 2, None));
+        assert!(TodoScope::User(1).is_visible(1, 2, Some(3)));
+        assert!(!TodoScope::User(1).is_visible(4, 2, Some(3)));
+        assert!(TodoScope::Channel(2).is_visible(4, 2, Some(3)));
+        assert!(!TodoScope::Channel(2).is_visible(1, 5, Some(3)));
+        assert!(TodoScope::Guild(3).is_visible(4, 5, Some(3)));
+        assert!(!TodoScope::Guild(3).is_visible(1, 2, None));
+    }
+
+    #[test]
+    fn test_encode() {
+        let todo = Todo::new(1, "a \"quoted\" todo");
+        let payload = encode(&todo).unwrap();
+        assert_eq!(
+            payload,
+            r#"{"id":1,"title":"a \"quoted\" todo","due":null,"assignee":null,"status":"open","created_by":null,"scope":"global","channel":null,"reminded":[]}"#
+        );
+        assert_eq!(decode::<Todo>(&payload), Some(todo));
+
+        let todo = Todo {
+            due: Some(
+                NaiveDateTime::parse_from_str("2024-01-02 18:00:00", date_format::FORMAT).unwrap(),
+            ),
+            assignee: Some(1234),
+            status: TodoStatus::Doing,
+            created_by: Some(5678),
+            scope: TodoScope::Channel(42),
+            channel: Some(42),
+            reminded: vec![TodoReminder::Upcoming],
+            ..Todo::new(2, "release")
+        };
+        let payload = encode(&todo).unwrap();
+        assert!(payload.contains(r#""reminded":["upcoming"]"#));
+        assert!(payload.contains(r#""scope":{"channel":42}"#));
+        assert!(payload.contains(r#""due":"2024-01-02 18:00:00""#));
+        assert!(payload.contains(r#""status":"doing""#));
+        assert_eq!(decode::<Todo>(&payload), Some(todo));
+
+        let last = RssLastDate {
+            date: NaiveDateTime::parse_from_str("2024-01-02 03:04:05", date_format::FORMAT)
+                .unwrap(),
+        };
+        let payload = encode(&last).unwrap();
+        assert_eq!(payload, r#"{"date":"2024-01-02 03:04:05"}"#);
+        assert_eq!(decode::<RssLastDate>(&payload), Some(last));
+    }
+}
@@ -0,0 +1,216 @@
+use std::convert::TryFrom;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::async_trait;
+
+use super::error::StoreError;
+use super::Store;
+
+/// ローカルの SQLite のファイルに保存する。record の id は行の id
+pub struct SqliteStore {
+    // rusqlite の Connection は Sync ではないので Mutex で共有する
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// path のデータベースを開いてテーブルを作る。":memory:" を渡すとメモリ上に作る
+    pub fn open(path: &str) -> Result<SqliteStore, StoreError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS records (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                prefix TEXT NOT NULL,
+                payload TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS records_prefix ON records (prefix);
+            CREATE TABLE IF NOT EXISTS migrations (
+                name TEXT PRIMARY KEY,
+                applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );",
+        )?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // 他のスレッドが panic していても SQLite 側の状態は壊れていないのでそのまま使う
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// name の移行が済んでいるか
+    pub fn is_migrated(&self, name: &str) -> Result<bool, StoreError> {
+        let migrated = self
+            .connection()
+            .query_row(
+                "SELECT 1 FROM migrations WHERE name = ?1",
+                params![name],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(migrated.is_some())
+    }
+
+    /// (prefix, payload) をまとめて追加して、name の移行が済んだことを記録する。
+    /// 既に name の移行が済んでいれば何もせずに false を返す。途中で失敗した場合は何も追加しない
+    pub fn import(&self, name: &str, records: &[(String, String)]) -> Result<bool, StoreError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        // 移行の記録と record の追加を同じトランザクションで行い、二重に取り込まないようにする
+        let inserted = transaction.execute(
+            "INSERT OR IGNORE INTO migrations (name) VALUES (?1)",
+            params![name],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        for (prefix, payload) in records {
+            transaction.execute(
+                "INSERT INTO records (prefix, payload) VALUES (?1, ?2)",
+                params![prefix, payload],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(true)
+    }
+}
+
+fn to_id(id: i64) -> u64 {
+    u64::try_from(id).unwrap_or_default()
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    async fn load(&self, prefix: &str) -> Result<Vec<(u64, String)>, StoreError> {
+        let connection = self.connection();
+        let mut statement =
+            connection.prepare("SELECT id, payload FROM records WHERE prefix = ?1 ORDER BY id")?;
+        let records = statement
+            .query_map(params![prefix], |row| Ok((to_id(row.get(0)?), row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    async fn insert(&self, prefix: &str, payload: String) -> Result<u64, StoreError> {
+        let connection = self.connection();
+        connection.execute(
+            "INSERT INTO records (prefix, payload) VALUES (?1, ?2)",
+            params![prefix, payload],
+        )?;
+        Ok(to_id(connection.last_insert_rowid()))
+    }
+
+    async fn update(&self, prefix: &str, id: u64, payload: String) -> Result<(), StoreError> {
+        self.connection().execute(
+            "UPDATE records SET payload = ?1 WHERE id = ?2 AND prefix = ?3",
+            params![payload, id as i64, prefix],
+        )?;
+        Ok(())
+    }
+
+    async fn delete(&self, prefix: &str, id: u64) -> Result<(), StoreError> {
+        self.connection().execute(
+            "DELETE FROM records WHERE id = ?1 AND prefix = ?2",
+            params![id as i64, prefix],
+        )?;
+        Ok(())
+    }
+
+    async fn replace(&self, prefix: &str, payload: String) -> Result<u64, StoreError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM records WHERE prefix = ?1", params![prefix])?;
+        transaction.execute(
+            "INSERT INTO records (prefix, payload) VALUES (?1, ?2)",
+            params![prefix, payload],
+        )?;
+        let id = to_id(transaction.last_insert_rowid());
+        transaction.commit()?;
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let first = store
+            .insert("todo_message", "1 first".to_string())
+            .await
+            .unwrap();
+        let second = store
+            .insert("todo_message", "2 second".to_string())
+            .await
+            .unwrap();
+        store
+            .insert("rss_link", "https://example.com".to_string())
+            .await
+            .unwrap();
+
+        store
+            .update("todo_message", second, "2 edited".to_string())
+            .await
+            .unwrap();
+        // prefix が違う場合は更新しない
+        store
+            .update("rss_link", first, "oops".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.load("todo_message").await.unwrap(),
+            vec![
+                (first, "1 first".to_string()),
+                (second, "2 edited".to_string())
+            ]
+        );
+
+        store.delete("todo_message", first).await.unwrap();
+        assert_eq!(store.load("todo_message").await.unwrap().len(), 1);
+        assert_eq!(store.load("rss_link").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_replace() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        store
+            .insert("rss_last_date", "2024-01-01 00:00:00".to_string())
+            .await
+            .unwrap();
+        store
+            .insert("rss_last_date", "2024-01-02 00:00:00".to_string())
+            .await
+            .unwrap();
+        let id = store
+            .replace("rss_last_date", "2024-01-03 00:00:00".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.load("rss_last_date").await.unwrap(),
+            vec![(id, "2024-01-03 00:00:00".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        assert!(!store.is_migrated("discord").unwrap());
+
+        let records = vec![
+            ("todo_message".to_string(), "1 foo".to_string()),
+            ("rss_link".to_string(), "https://example.com".to_string()),
+        ];
+        assert!(store.import("discord", &records).unwrap());
+        assert!(store.is_migrated("discord").unwrap());
+        assert_eq!(store.load("todo_message").await.unwrap().len(), 1);
+
+        // 2 回目は移行が済んでいるので何もしない
+        assert!(!store.import("discord", &records).unwrap());
+        assert_eq!(store.load("todo_message").await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// URL の parse に失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// scheme がない相対参照を基準の URL なしで parse しようとした
+    RelativeUrlWithoutBase,
+    /// scheme に使えない文字が含まれている
+    InvalidScheme(String),
+    /// http と https の URL に host がない
+    EmptyHost,
+    /// host に使えない文字が含まれている、もしくは IDNA の変換に失敗した
+    InvalidHost(String),
+    /// [] で囲まれた host が IPv6 アドレスとして正しくない
+    InvalidIpv6(String),
+    /// port が数字でない、もしくは 65535 を超えている
+    InvalidPort(String),
+    /// % の後に 16 進数が2桁続いていない
+    InvalidPercentEncoding,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::RelativeUrlWithoutBase => write!(f, "relative url without a base"),
+            ParseError::InvalidScheme(scheme) => write!(f, "invalid scheme: {}", scheme),
+            ParseError::EmptyHost => write!(f, "empty host"),
+            ParseError::InvalidHost(host) => write!(f, "invalid host: {}", host),
+            ParseError::InvalidIpv6(host) => write!(f, "invalid ipv6 address: {}", host),
+            ParseError::InvalidPort(port) => write!(f, "invalid port: {}", port),
+            ParseError::InvalidPercentEncoding => write!(f, "invalid percent encoding"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
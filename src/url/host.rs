@@ -0,0 +1,137 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use super::error::ParseError;
+use super::punycode;
+
+/// URL の host 部分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Host {
+    /// ASCII に変換して小文字にしたドメイン名
+    Domain(String),
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+}
+
+impl Host {
+    /// host を parse する。IPv6 は [] で囲まれている必要がある。
+    /// ドメイン名は小文字にして、ASCII 以外の文字を含むラベルは IDNA で xn-- の形に変換する。
+    pub fn parse(input: &str) -> Result<Host, ParseError> {
+        if let Some(inner) = input.strip_prefix('[') {
+            return match inner.strip_suffix(']').map(|inner| inner.parse()) {
+                Some(Ok(addr)) => Ok(Host::Ipv6(addr)),
+                _ => Err(ParseError::InvalidIpv6(input.to_string())),
+            };
+        }
+
+        // 全角のドットも区切りとして扱う
+        let labels = input
+            .split(['.', '。', '．', '｡'])
+            .map(to_ascii_label)
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| ParseError::InvalidHost(input.to_string()))?;
+        // 末尾のドット (FQDN) 以外の空のラベルは認めない
+        let last = labels.len() - 1;
+        if labels
+            .iter()
+            .enumerate()
+            .any(|(i, label)| label.is_empty() && (i != last || last == 0))
+        {
+            return Err(ParseError::InvalidHost(input.to_string()));
+        }
+
+        // 最後のラベルが数字だけなら IPv4 アドレスとして扱う
+        let is_numeric =
+            |label: &String| !label.is_empty() && label.bytes().all(|b| b.is_ascii_digit());
+        if labels
+            .iter()
+            .rev()
+            .find(|label| !label.is_empty())
+            .is_some_and(is_numeric)
+        {
+            return match input.parse() {
+                Ok(addr) => Ok(Host::Ipv4(addr)),
+                Err(_) => Err(ParseError::InvalidHost(input.to_string())),
+            };
+        }
+
+        Ok(Host::Domain(labels.join(".")))
+    }
+}
+
+// ラベルを小文字の ASCII にする。使えない文字が含まれている場合は None を返す。
+fn to_ascii_label(label: &str) -> Option<String> {
+    let label = label.to_lowercase();
+    let label = if label.is_ascii() {
+        label
+    } else {
+        format!("xn--{}", punycode::encode(&label)?)
+    };
+    let valid = label
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid || label.len() > 63 {
+        return None;
+    }
+    Some(label)
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Domain(domain) => write!(f, "{}", domain),
+            Host::Ipv4(addr) => write!(f, "{}", addr),
+            Host::Ipv6(addr) => write!(f, "[{}]", addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Host::parse("Example.COM"),
+            Ok(Host::Domain("example.com".to_string()))
+        );
+        assert_eq!(
+            Host::parse("日本語。JP"),
+            Ok(Host::Domain("xn--wgv71a119e.jp".to_string()))
+        );
+        assert_eq!(
+            Host::parse("127.0.0.1"),
+            Ok(Host::Ipv4(Ipv4Addr::new(127, 0, 0, 1)))
+        );
+        assert_eq!(Host::parse("[::1]"), Ok(Host::Ipv6(Ipv6Addr::LOCALHOST)));
+        assert_eq!(
+            Host::parse("[2001:DB8:0:0:0:0:0:1]").unwrap().to_string(),
+            "[2001:db8::1]"
+        );
+        assert!(matches!(
+            Host::parse("[::1"),
+            Err(ParseError::InvalidIpv6(_))
+        ));
+        assert!(matches!(
+            Host::parse("[fe80::1%eth0]"),
+            Err(ParseError::InvalidIpv6(_))
+        ));
+        assert!(matches!(
+            Host::parse("999.0.0.1"),
+            Err(ParseError::InvalidHost(_))
+        ));
+        assert!(matches!(
+            Host::parse("exa mple.com"),
+            Err(ParseError::InvalidHost(_))
+        ));
+        assert!(matches!(
+            Host::parse("example..com"),
+            Err(ParseError::InvalidHost(_))
+        ));
+        assert_eq!(
+            Host::parse("example.com."),
+            Ok(Host::Domain("example.com.".to_string()))
+        );
+    }
+}
@@ -1 +1,4 @@
+pub mod error;
+pub mod host;
+mod punycode;
 pub mod url;
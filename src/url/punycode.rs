@@ -0,0 +1,94 @@
+// RFC 3492 の Punycode。IDNA で ASCII 以外の文字を含むラベルを xn-- の形に変換するのに使う。
+
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+fn adapt(delta: u32, num_points: u32, first_time: bool) -> u32 {
+    let mut delta = if first_time { delta / DAMP } else { delta / 2 };
+    delta += delta / num_points;
+    let mut k = 0;
+    while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+    k + (((BASE - T_MIN + 1) * delta) / (delta + SKEW))
+}
+
+fn digit(d: u32) -> char {
+    match d {
+        0..=25 => (b'a' + d as u8) as char,
+        _ => (b'0' + (d - 26) as u8) as char,
+    }
+}
+
+/// ラベルを Punycode に変換する。xn-- はつけない。計算がオーバーフローした場合は None を返す。
+pub fn encode(input: &str) -> Option<String> {
+    let code_points = input.chars().map(|c| c as u32).collect::<Vec<u32>>();
+    let mut output = input.chars().filter(|c| c.is_ascii()).collect::<String>();
+    let basic = output.len() as u32;
+    if basic > 0 {
+        output.push('-');
+    }
+
+    let mut n = INITIAL_N;
+    let mut delta: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    let mut handled = basic;
+    while (handled as usize) < code_points.len() {
+        // まだ処理していない中で最小のコードポイントまで delta を進める
+        let m = code_points.iter().copied().filter(|&c| c >= n).min()?;
+        delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+        n = m;
+
+        for &c in &code_points {
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+            if c != n {
+                continue;
+            }
+            let mut q = delta;
+            let mut k = BASE;
+            loop {
+                let t = if k <= bias {
+                    T_MIN
+                } else if k >= bias + T_MAX {
+                    T_MAX
+                } else {
+                    k - bias
+                };
+                if q < t {
+                    break;
+                }
+                output.push(digit(t + (q - t) % (BASE - t)));
+                q = (q - t) / (BASE - t);
+                k += BASE;
+            }
+            output.push(digit(q));
+            bias = adapt(delta, handled + 1, handled == basic);
+            delta = 0;
+            handled += 1;
+        }
+        delta = delta.checked_add(1)?;
+        n += 1;
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("bücher"), Some("bcher-kva".to_string()));
+        assert_eq!(encode("münchen"), Some("mnchen-3ya".to_string()));
+        assert_eq!(encode("日本語"), Some("wgv71a119e".to_string()));
+        assert_eq!(encode("example"), Some("example-".to_string()));
+    }
+}
@@ -14,6 +14,44 @@ pub fn encode(input: &str) -> String {
     encoded
 }
 
+/// application/x-www-form-urlencoded の形式でエンコードする。空白は + にする。
+#[allow(dead_code)]
+pub fn form_encode(input: &str) -> String {
+    let mut encoded = String::new();
+
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'*' => {
+                encoded.push(byte as char);
+            }
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+/// key と value の組を application/x-www-form-urlencoded の文字列にする
+pub fn form_encode_pairs<I, K, V>(pairs: I) -> String
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    pairs
+        .into_iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                form_encode(key.as_ref()),
+                form_encode(value.as_ref())
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -45,4 +83,14 @@ mod tests {
         assert_eq!(encode("a$d$c"), "a%24d%24c");
         assert_eq!(encode("a`d`c"), "a%60d%60c");
     }
+
+    #[test]
+    fn test_form_encode() {
+        assert_eq!(form_encode("a b+c"), "a+b%2Bc");
+        assert_eq!(form_encode("あ*~"), "%E3%81%82*%7E");
+        assert_eq!(
+            form_encode_pairs([("q", "rust 言語"), ("lang", "ja&en")]),
+            "q=rust+%E8%A8%80%E8%AA%9E&lang=ja%26en"
+        );
+    }
 }
@@ -1,14 +1,22 @@
 use std::env;
+use std::time::Duration;
 
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use serenity::client::Context;
 use tracing::error;
 
-use crate::http::client::HttpClient;
+use crate::http::client::{HttpClient, Method};
 use std::error::Error;
 
-use super::get_db_channel::get_db_channel;
+use crate::store::get_store;
+use crate::store::record::AtprotoLastDate;
+
+#[derive(Serialize)]
+struct CreateSessionRequest {
+    identifier: String,
+    password: String,
+}
 
 #[derive(Deserialize)]
 #[allow(non_snake_case)]
@@ -25,8 +33,9 @@ pub struct CreateSessionResponse {
 
 // TODO: 全体的にこのファイルは共通化する。今は feed とる以外しないから一旦ベタで書いていく。
 // TODO: tracing でログを出すようにする。
-async fn create_session() -> Result<CreateSessionResponse, Box<dyn std::error::Error>> {
-    let mut client = HttpClient::new();
+async fn create_session(
+    client: &HttpClient,
+) -> Result<CreateSessionResponse, Box<dyn std::error::Error>> {
     let url = "https://bsky.social/xrpc/com.atproto.server.createSession";
     let identifier = match env::var("BSKY_IDENTIFIER") {
         Ok(identifier) => identifier,
@@ -48,15 +57,16 @@ async fn create_session() -> Result<CreateSessionResponse, Box<dyn std::error::E
             )));
         }
     };
-    let body = format!(
-        r#"{{"identifier":"{}","password":"{}"}}"#,
-        identifier, password
-    );
+    let body = CreateSessionRequest {
+        identifier,
+        password,
+    };
 
     let response = match client
-        .set_header("Content-Type", "application/json")
-        .set_header("Accept", "application/json")
-        .post(url, body)
+        .request(Method::Post, url)
+        .header("Accept", "application/json")
+        .json(&body)
+        .send()
         .await
     {
         Ok(response) => response,
@@ -138,19 +148,21 @@ pub struct Record {
 // pub struct Label {}
 
 async fn get_feed() -> Result<Body, Box<dyn std::error::Error>> {
-    let session = create_session().await?;
+    // createSession と getFeed は同じホストなので接続を使い回す
     let mut client = HttpClient::new();
-    let params = "at://did:plc:c2f75sprlocrelfiftzblj6z/app.bsky.feed.generator/aaair5qf7emhe";
-    let url = format!(
-        "https://bsky.social/xrpc/app.bsky.feed.getFeed?feed={}",
-        params
-    );
+    client.keep_alive(Duration::from_secs(30));
+    let session = create_session(&client).await?;
+    let feed = "at://did:plc:c2f75sprlocrelfiftzblj6z/app.bsky.feed.generator/aaair5qf7emhe";
 
     let response = match client
-        .header_authorization(session.accessJwt)
-        .set_header("Content-Type", "application/json")
-        .set_header("Accept", "application/json")
-        .get(&url)
+        .request(
+            Method::Get,
+            "https://bsky.social/xrpc/app.bsky.feed.getFeed",
+        )
+        .query("feed", feed)
+        .bearer_auth(&session.accessJwt)
+        .header("Accept", "application/json")
+        .send()
         .await
     {
         Ok(response) => response,
@@ -180,7 +192,6 @@ async fn get_feed() -> Result<Body, Box<dyn std::error::Error>> {
 pub async fn fetch_atproto(ctx: &Context) -> Result<Vec<Feed>, Box<dyn std::error::Error>> {
     let res = get_feed().await?;
     let last_date = get_last_date(ctx).await?;
-    let last_date = NaiveDateTime::parse_from_str(&last_date, "%Y-%m-%d %H:%M:%S")?;
 
     let feeds = res
         .feed
@@ -198,35 +209,12 @@ pub async fn fetch_atproto(ctx: &Context) -> Result<Vec<Feed>, Box<dyn std::erro
     Ok(feeds)
 }
 
-async fn get_last_date(ctx: &Context) -> Result<String, Box<dyn Error>> {
-    let db_channel = get_db_channel(ctx).await?;
-
-    let messages = match db_channel
-        .messages(&ctx.http, |retriever| retriever.limit(100))
-        .await
-    {
-        Ok(messages) => messages
-            .into_iter()
-            .filter(|message| message.content.starts_with("atproto_last_date"))
-            .collect::<Vec<_>>(),
-        Err(_) => {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "DBチャンネルが見つかりません。",
-            )))
-        }
-    };
-
-    // last_date は基本的に1つしか存在しないはずなので、0番目を取得
-    let last_date = match messages.first() {
-        // rss_last_date %Y-%m-%d %H:%M:%S という形式で保存されているので、それの日付部分だけを取得
-        // nth(1) は %Y-%m-%d の部分, nth(2) は %H:%M:%S の部分
-        Some(message) => {
-            let date = message.content.split_whitespace().nth(1).unwrap();
-            let time = message.content.split_whitespace().nth(2).unwrap();
-            format!("{} {}", date, time)
-        }
-        None => chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+// 最後に取得した日時。まだ一度も取得していない場合は現在時刻
+async fn get_last_date(ctx: &Context) -> Result<NaiveDateTime, Box<dyn Error>> {
+    let store = get_store(ctx).await?;
+    let last_date = match store.get::<AtprotoLastDate>().await? {
+        Some(last) => last.date,
+        None => chrono::Utc::now().naive_utc(),
     };
     Ok(last_date)
 }
@@ -1,8 +1,20 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::env;
 use tracing::error;
 
-use crate::http::client::HttpClient;
+use crate::http::client::{HttpClient, Method};
+
+#[derive(Serialize)]
+struct ChatGPTRequestMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct ChatGPTRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatGPTRequestMessage<'a>>,
+}
 
 #[derive(Deserialize)]
 struct ChatGPTMessage {
@@ -20,21 +32,21 @@ struct ChatGPTResponse {
 }
 
 pub async fn fetch_chatgpt(content: String, prompts: Vec<String>) -> String {
-    let prompts = prompts
+    let mut messages = prompts
         .iter()
-        .map(|p| {
-            format!(
-                r#"{{ "role": "system", "content": "{}" }},"#,
-                p.replace("\"", "").replace("\n", "")
-            )
+        .map(|p| ChatGPTRequestMessage {
+            role: "system",
+            content: p,
         })
-        .collect::<Vec<String>>()
-        .join("");
-
-    let request_body = format!(
-        r#"{{ "model": "gpt-4o", "messages": [{}{{ "role": "user", "content": "{}" }}] }}"#,
-        prompts, content
-    );
+        .collect::<Vec<_>>();
+    messages.push(ChatGPTRequestMessage {
+        role: "user",
+        content: &content,
+    });
+    let request_body = ChatGPTRequest {
+        model: "gpt-4o",
+        messages,
+    };
 
     let open_api_key = match env::var("OPENAI_API_KEY") {
         Ok(key) => key,
@@ -44,11 +56,12 @@ pub async fn fetch_chatgpt(content: String, prompts: Vec<String>) -> String {
         }
     };
 
-    let mut client = HttpClient::new();
+    let client = HttpClient::new();
     let response = match client
-        .set_header("Content-Type", "application/json")
-        .header_authorization(open_api_key)
-        .post("https://api.openai.com/v1/chat/completions", request_body)
+        .request(Method::Post, "https://api.openai.com/v1/chat/completions")
+        .bearer_auth(&open_api_key)
+        .json(&request_body)
+        .send()
         .await
     {
         Ok(response) => response,
@@ -61,7 +74,7 @@ pub async fn fetch_chatgpt(content: String, prompts: Vec<String>) -> String {
     let body = match response.json::<ChatGPTResponse>().await {
         Ok(body) => body,
         Err(e) => {
-            error!("failed to parse json: {:?}", e);
+            error!("failed to parse json: {:?}, body: {}", e, response.text());
             return "コンテンツの取得に失敗しました。".to_string();
         }
     };
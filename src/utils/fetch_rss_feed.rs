@@ -2,48 +2,27 @@ use chrono::{DateTime, NaiveDateTime};
 use rss::{Channel, Item};
 use serenity::client::Context;
 use std::error::Error;
+use std::time::Duration;
 use tracing::{info, warn};
 
-use super::get_db_channel::get_db_channel;
-use crate::http::client::HttpClient;
+use crate::http::client::{HttpClient, RetryPolicy};
+use crate::store::get_store;
+use crate::store::record::{RssLastDate, RssLink};
 
-// rss のリストを #db チャンネルから `rss_link` という prefix がついてるものを取得。
+// 登録されている rss のリストを取得。
 async fn get_rss_list(ctx: &Context) -> Result<Vec<String>, Box<dyn Error>> {
-    let db_channel = get_db_channel(ctx).await?;
-
-    let messages = match db_channel
-        .messages(&ctx.http, |retriever| retriever.limit(100))
-        .await
-    {
-        Ok(messages) => messages
-            .into_iter()
-            .filter(|message| message.content.starts_with("rss_link"))
-            .collect::<Vec<_>>(),
-        Err(_) => {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "DBチャンネルが見つかりません。",
-            )))
-        }
-    };
-
-    let rss_list = messages
-        .iter()
-        .map(|message| {
-            message
-                .content
-                .split_whitespace()
-                .nth(1)
-                .unwrap()
-                .to_string()
-        })
+    let store = get_store(ctx).await?;
+    let rss_list = store
+        .list::<RssLink>()
+        .await?
+        .into_iter()
+        .map(|stored| stored.record.link)
         .collect::<Vec<String>>();
 
     Ok(rss_list)
 }
 
-async fn fetch_feed(url: String) -> Result<Channel, Box<dyn Error>> {
-    let client = HttpClient::new();
+async fn fetch_feed(client: &HttpClient, url: String) -> Result<Channel, Box<dyn Error>> {
     let result = match client.get(&url).await {
         Ok(content) => content,
         Err(_) => {
@@ -53,40 +32,30 @@ async fn fetch_feed(url: String) -> Result<Channel, Box<dyn Error>> {
             )))
         }
     };
-    let content = result.body.as_bytes();
-    let channel = Channel::read_from(&content[..])?;
+    // XML の prolog の encoding は rss 側で解釈されるので、バイト列のまま渡す
+    let channel = Channel::read_from(result.bytes())?;
     Ok(channel)
 }
 
-async fn get_last_date(ctx: &Context) -> Result<String, Box<dyn Error>> {
-    let db_channel = get_db_channel(ctx).await?;
-
-    let messages = match db_channel
-        .messages(&ctx.http, |retriever| retriever.limit(100))
-        .await
-    {
-        Ok(messages) => messages
-            .into_iter()
-            .filter(|message| message.content.starts_with("rss_last_date"))
-            .collect::<Vec<_>>(),
-        Err(_) => {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "DBチャンネルが見つかりません。",
-            )))
+// リダイレクトを辿った後の feed の URL を取得する。取得できなかった場合は元の URL をそのまま返す。
+pub async fn resolve_feed_url(url: &str) -> String {
+    let client = HttpClient::new();
+    match client.get(url).await {
+        Ok(response) if !response.url.is_empty() => response.url,
+        Ok(_) => url.to_string(),
+        Err(why) => {
+            warn!("failed to resolve feed url {}: {:?}", url, why);
+            url.to_string()
         }
-    };
+    }
+}
 
-    // last_date は基本的に1つしか存在しないはずなので、0番目を取得
-    let last_date = match messages.first() {
-        // rss_last_date %Y-%m-%d %H:%M:%S という形式で保存されているので、それの日付部分だけを取得
-        // nth(1) は %Y-%m-%d の部分, nth(2) は %H:%M:%S の部分
-        Some(message) => {
-            let date = message.content.split_whitespace().nth(1).unwrap();
-            let time = message.content.split_whitespace().nth(2).unwrap();
-            format!("{} {}", date, time)
-        }
-        None => chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+// 最後に取得した日時。まだ一度も取得していない場合は現在時刻
+async fn get_last_date(ctx: &Context) -> Result<NaiveDateTime, Box<dyn Error>> {
+    let store = get_store(ctx).await?;
+    let last_date = match store.get::<RssLastDate>().await? {
+        Some(last) => last.date,
+        None => chrono::Utc::now().naive_utc(),
     };
     Ok(last_date)
 }
@@ -95,13 +64,18 @@ pub async fn fetch_rss_feed(ctx: &Context) -> Result<Vec<Item>, Box<dyn Error>>
     let rss_list = get_rss_list(ctx).await?;
 
     let last_date = get_last_date(ctx).await?;
-    let last_date = NaiveDateTime::parse_from_str(&last_date, "%Y-%m-%d %H:%M:%S")?;
     // ラグ対策として半日巻き戻す
     let last_date = last_date - chrono::Duration::hours(12);
 
+    // 同じホストの feed が多いので接続を使い回す
+    let mut client = HttpClient::new();
+    client
+        .keep_alive(Duration::from_secs(30))
+        .retry(RetryPolicy::new(2));
+
     let mut items = Vec::new();
     for url in rss_list {
-        let channel = match fetch_feed(url).await {
+        let channel = match fetch_feed(&client, url).await {
             Ok(channel) => channel,
             Err(_) => continue,
         };
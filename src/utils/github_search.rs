@@ -1,7 +1,7 @@
 use serde::Deserialize;
 use tracing::{error, warn};
 
-use crate::http::client::{HttpClient, StatusCode};
+use crate::http::client::{HttpClient, RetryPolicy, StatusCode};
 
 #[derive(Deserialize, Debug)]
 pub struct GoogleItem {
@@ -37,8 +37,8 @@ struct GithubTrend {
 pub async fn github_search(language: &str) -> Result<Vec<GithubTrendItem>, String> {
     let url = format!("https://api.github.com/search/repositories?q=language:{language}&order=desc&per_page=10&since=daily");
 
-    let client = HttpClient::new();
-    let response = client.get(&url);
+    let mut client = HttpClient::new();
+    let response = client.retry(RetryPolicy::new(3)).get(&url);
 
     let response = match response.await {
         Ok(response) => response,
@@ -48,14 +48,29 @@ pub async fn github_search(language: &str) -> Result<Vec<GithubTrendItem>, Strin
         }
     };
 
-    let _ = match response.status_code {
-        StatusCode::OK => (),
-        StatusCode::NotFound => {
+    match response.status_code {
+        status if status.is_success() => (),
+        StatusCode::NOT_FOUND => {
             error!("github api not found");
             return Err("リソースが見つかりませんでした。".to_string());
         }
-        _ => {
-            error!("github api error");
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => {
+            error!("github api rate limited: {}", response.status_code);
+            return Err(
+                "GitHub API へのリクエスト超過です。しばらくしてからやり直してください。"
+                    .to_string(),
+            );
+        }
+        status if status.is_client_error() => {
+            error!("github api client error: {}", status);
+            return Err("リクエストが不正です。".to_string());
+        }
+        status if status.is_server_error() => {
+            error!("github api server error: {}", status);
+            return Err("GitHub でサーバーエラーが発生しました。".to_string());
+        }
+        status => {
+            error!("github api error: {}", status);
             return Err("エラーが発生しました。".to_string());
         }
     };
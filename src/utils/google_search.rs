@@ -3,8 +3,11 @@ use std::env;
 use tracing::error;
 
 use crate::{
-    http::client::{HttpClient, StatusCode},
-    utils::encode::encode,
+    http::{
+        client::{HttpClient, RetryPolicy, StatusCode},
+        error::HttpError,
+    },
+    url::url::Url,
 };
 
 #[derive(Deserialize, Debug)]
@@ -24,17 +27,6 @@ pub async fn google_search(
     search_type: &str,
     site: &str,
 ) -> Result<Vec<GoogleItem>, String> {
-    // web を明示するとエラーになるので省略する
-    let search_type = if search_type == "image" {
-        format!("&searchType={search_type}")
-    } else {
-        "".to_string()
-    };
-    let site = if site == "" {
-        "".to_string()
-    } else {
-        format!("+site:{site}", site = site)
-    };
     let search_engine_id = match env::var("SEARCH_ENGINE_ID") {
         Ok(id) => id,
         Err(_) => {
@@ -50,29 +42,71 @@ pub async fn google_search(
         }
     };
 
-    let url = format!(
-    "https://www.googleapis.com/customsearch/v1?cx={search_engine_id}&key={api_key}&hl=ja{search_type}&q={}{site}", encode(q));
+    let mut url = match Url::parse("https://www.googleapis.com/customsearch/v1") {
+        Ok(url) => url,
+        Err(why) => {
+            error!("failed to parse google search url: {}", why);
+            return Err("Google 検索の URL が不正です。".to_string());
+        }
+    };
+    url.set_query_pairs([
+        ("cx", search_engine_id.as_str()),
+        ("key", &api_key),
+        ("hl", "ja"),
+    ]);
+    // web を明示するとエラーになるので省略する
+    if search_type == "image" {
+        url.append_pair("searchType", search_type);
+    }
+    if site.is_empty() {
+        url.append_pair("q", q);
+    } else {
+        url.append_pair("q", &format!("{} site:{}", q, site));
+    }
 
-    let client = HttpClient::new();
-    let result = match client.get(&url).await {
+    // 429 や 5xx は時間を置くと成功することが多いのでやり直す
+    let mut client = HttpClient::new();
+    let result = match client
+        .retry(RetryPolicy::new(3))
+        .get(&url.to_string())
+        .await
+    {
         Ok(result) => match result.status_code {
             StatusCode::OK => result,
-            StatusCode::BadRequest => return Err("リクエストが不正です。".to_string()),
-            StatusCode::Unauthorized => return Err("認証に失敗しました。".to_string()),
-            StatusCode::Forbidden => return Err("アクセス権限がありません。".to_string()),
-            StatusCode::NotFound => return Err("リソースが見つかりませんでした。".to_string()),
-            StatusCode::TooManyRequests => return Err(
+            StatusCode::BAD_REQUEST => return Err("リクエストが不正です。".to_string()),
+            StatusCode::UNAUTHORIZED => return Err("認証に失敗しました。".to_string()),
+            StatusCode::FORBIDDEN => return Err("アクセス権限がありません。".to_string()),
+            StatusCode::NOT_FOUND => return Err("リソースが見つかりませんでした。".to_string()),
+            StatusCode::TOO_MANY_REQUESTS => return Err(
                 "Google Search API へのリクエスト超過です。しばらくしてからやり直してください。"
                     .to_string(),
             ),
+            status if status.is_server_error() => {
+                return Err(format!(
+                    "Google Search API でサーバーエラーが発生しました。status: {}",
+                    status.as_u16()
+                ));
+            }
             status => {
-                return Err(
-                    format!("予期しないエラーが発生しました。status: {}", status as u16)
-                        .to_string(),
-                );
+                return Err(format!(
+                    "予期しないエラーが発生しました。status: {}",
+                    status.as_u16()
+                ));
             }
         },
-        Err(_) => return Err("Google 検索でエラーが発生しました。".to_string()),
+        Err(why) => {
+            error!("google search request failed: {}", why);
+            let message = match why {
+                HttpError::InvalidUrl(_) => "Google 検索の URL が不正です。",
+                HttpError::Dns(_) => "Google 検索の名前解決に失敗しました。",
+                HttpError::Connect(_) => "Google 検索に接続できませんでした。",
+                HttpError::Tls(_) => "Google 検索との TLS 接続に失敗しました。",
+                HttpError::Timeout(_) => "Google 検索がタイムアウトしました。",
+                HttpError::Protocol(_) => "Google 検索から不正なレスポンスが返されました。",
+                HttpError::Io(_) => "Google 検索でエラーが発生しました。",
+            };
+            return Err(message.to_string());
+        }
     };
     let body = match result.json::<GoogleResponse>().await {
         Ok(body) => body,
@@ -1,31 +1,137 @@
-fn hex_to_u8(hex: u8) -> u8 {
-    match hex {
-        b'0'..=b'9' => hex - b'0',
-        b'a'..=b'f' => hex - b'a' + 10,
-        b'A'..=b'F' => hex - b'A' + 10,
-        _ => panic!("Invalid hex digit: {}", hex),
+use std::fmt;
+
+/// strict な percent_decode が失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// position の % の後に 16 進数が2桁続いていない
+    InvalidEscape { position: usize },
+    /// デコードした結果が UTF-8 として正しくない
+    InvalidUtf8,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidEscape { position } => {
+                write!(f, "invalid percent escape at {}", position)
+            }
+            DecodeError::InvalidUtf8 => write!(f, "decoded bytes are not valid utf-8"),
+        }
     }
 }
 
-pub fn percent_decode(input: &str) -> String {
-    use std::str;
+impl std::error::Error for DecodeError {}
 
-    let mut out = Vec::new();
-    let mut i = 0;
+fn hex_to_u8(hex: u8) -> Option<u8> {
+    match hex {
+        b'0'..=b'9' => Some(hex - b'0'),
+        b'a'..=b'f' => Some(hex - b'a' + 10),
+        b'A'..=b'F' => Some(hex - b'A' + 10),
+        _ => None,
+    }
+}
+
+// %XX をバイトに戻す。strict の場合は不正な % でエラーにし、そうでなければそのまま残す。
+fn decode_bytes(input: &str, strict: bool) -> Result<Vec<u8>, DecodeError> {
     let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
     while i < bytes.len() {
-        match bytes[i] {
-            b'%' if i + 2 < bytes.len() => {
-                let h = hex_to_u8(bytes[i + 1]);
-                let l = hex_to_u8(bytes[i + 2]);
-                out.push((h << 4) | l);
-                i += 3;
-            }
-            b => {
-                out.push(b);
-                i += 1;
+        if bytes[i] == b'%' {
+            let escape = match (bytes.get(i + 1), bytes.get(i + 2)) {
+                (Some(&h), Some(&l)) => hex_to_u8(h).zip(hex_to_u8(l)),
+                _ => None,
+            };
+            match escape {
+                Some((h, l)) => {
+                    out.push((h << 4) | l);
+                    i += 3;
+                    continue;
+                }
+                None if strict => return Err(DecodeError::InvalidEscape { position: i }),
+                None => (),
             }
         }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    Ok(out)
+}
+
+/// %XX をデコードする。不正な % や UTF-8 として正しくない結果はエラーにする。
+#[allow(dead_code)]
+pub fn percent_decode(input: &str) -> Result<String, DecodeError> {
+    let out = decode_bytes(input, true)?;
+    String::from_utf8(out).map_err(|_| DecodeError::InvalidUtf8)
+}
+
+/// %XX をデコードする。不正な % はそのまま残し、UTF-8 として正しくないバイトは U+FFFD に置き換える。
+pub fn percent_decode_lossy(input: &str) -> String {
+    let out = decode_bytes(input, false).unwrap_or_default();
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// application/x-www-form-urlencoded の値をデコードする。+ は空白として扱う。
+pub fn form_decode(input: &str) -> String {
+    percent_decode_lossy(&input.replace('+', " "))
+}
+
+/// application/x-www-form-urlencoded の文字列を key と value の組に分けてデコードする。
+/// 順番と重複した key はそのまま残す。
+pub fn parse_form(input: &str) -> Vec<(String, String)> {
+    input
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            let key = form_decode(pair.next().unwrap_or(""));
+            let value = form_decode(pair.next().unwrap_or(""));
+            (key, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b"), Ok("a b".to_string()));
+        assert_eq!(percent_decode("%E3%81%82%e3%81%84"), Ok("あい".to_string()));
+        // 末尾の %XX もデコードする
+        assert_eq!(percent_decode("100%25"), Ok("100%".to_string()));
+        assert_eq!(percent_decode("a+b"), Ok("a+b".to_string()));
+        assert_eq!(
+            percent_decode("a%zzb"),
+            Err(DecodeError::InvalidEscape { position: 1 })
+        );
+        assert_eq!(
+            percent_decode("a%2"),
+            Err(DecodeError::InvalidEscape { position: 1 })
+        );
+        assert_eq!(percent_decode("%FF"), Err(DecodeError::InvalidUtf8));
+    }
+
+    #[test]
+    fn test_percent_decode_lossy() {
+        assert_eq!(percent_decode_lossy("100%"), "100%");
+        assert_eq!(percent_decode_lossy("a%zz%41"), "a%zzA");
+        assert_eq!(percent_decode_lossy("%FFa"), "\u{FFFD}a");
+        assert_eq!(percent_decode_lossy("%E3%81%82"), "あ");
+    }
+
+    #[test]
+    fn test_form() {
+        assert_eq!(form_decode("rust+%E8%A8%80%E8%AA%9E%2B"), "rust 言語+");
+        assert_eq!(
+            parse_form("q=a+b&lang=ja%26en&&q=%&flag"),
+            vec![
+                ("q".to_string(), "a b".to_string()),
+                ("lang".to_string(), "ja&en".to_string()),
+                ("q".to_string(), "%".to_string()),
+                ("flag".to_string(), "".to_string()),
+            ]
+        );
     }
-    str::from_utf8(&out).unwrap().to_string()
 }
@@ -3,6 +3,8 @@ use tracing::error;
 
 use super::google_search::google_search;
 use crate::http::client::HttpClient;
+use crate::url::url::Url;
+use crate::utils::percent_decode::percent_decode_lossy;
 
 #[derive(Deserialize)]
 pub struct Pages {
@@ -38,9 +40,24 @@ pub async fn wikipedia_search(search_text: &str) -> Result<(Response, String), S
     };
 
     let text = wikipedia.replace("https://ja.wikipedia.org/wiki/", "");
-    let url = format!("https://ja.wikipedia.org/w/api.php?format=json&action=query&prop=extracts&exintro&explaintext&redirects=1&titles={}", text);
+    let mut url = match Url::parse("https://ja.wikipedia.org") {
+        Ok(url) => url,
+        Err(why) => {
+            error!("failed to parse wikipedia url: {}", why);
+            return Err("Wikipedia の URL が不正です".to_string());
+        }
+    };
+    url.set_path_segments(["w", "api.php"]).set_query_pairs([
+        ("format", "json"),
+        ("action", "query"),
+        ("prop", "extracts"),
+        ("exintro", ""),
+        ("explaintext", ""),
+        ("redirects", "1"),
+        ("titles", &percent_decode_lossy(&text)),
+    ]);
     let client = HttpClient::new();
-    let response = match client.get(&url).await {
+    let response = match client.get(&url.to_string()).await {
         Ok(response) => response,
         Err(e) => {
             error!("failed to get wikipedia: {:?}", e);